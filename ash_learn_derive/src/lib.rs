@@ -0,0 +1,207 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, punctuated::Punctuated, spanned::Spanned, Attribute, Data, DeriveInput,
+    Expr, Field, Fields, Lit, Meta, Token, Type,
+};
+
+// implements crate::vulkan::vertex::VertexInput for a #[repr(C)] struct,
+// every field becomes one attribute, nested arrays such as [[f32; 4]; 4] one per column
+//
+// #[instance] on the struct makes it a per-instance binding,
+// #[location = N] on a field places it and the fields after it from location N on,
+// #[format = "R8G8B8A8_UINT"] on a field overrides the format derived from its type
+#[proc_macro_derive(VertexInput, attributes(instance, location, format))]
+pub fn derive_vertex_input(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match vertex_input(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn vertex_input(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    if !is_repr_c(&input.attrs)? {
+        return Err(syn::Error::new(input.ident.span(), "VertexInput requires #[repr(C)] so the field offsets are stable"));
+    }
+
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(input.ident.span(), "VertexInput can only be derived for structs"));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new(input.ident.span(), "VertexInput requires named fields"));
+    };
+
+    let input_rate = if input.attrs.iter().any(|attr| attr.path().is_ident("instance")) {
+        quote!(INSTANCE)
+    } else {
+        quote!(VERTEX)
+    };
+
+    let mut location = 0;
+    let mut attributes = Vec::new();
+    for field in &fields.named {
+        let ident = field.ident.as_ref().unwrap();
+
+        if let Some(overridden) = location_attr(field)? {
+            location = overridden;
+        }
+
+        let layout = match format_attr(field)? {
+            Some(format) => FieldLayout {
+                format,
+                columns: 1,
+                column_size: 0,
+            },
+            None => field_layout(&field.ty)?,
+        };
+
+        let format = format_ident!("{}", layout.format);
+        for column in 0..layout.columns {
+            let column_offset = column * layout.column_size;
+            attributes.push(quote! {
+                ::ash::vk::VertexInputAttributeDescription {
+                    location: #location,
+                    binding,
+                    format: ::ash::vk::Format::#format,
+                    offset: ::core::mem::offset_of!(Self, #ident) as u32 + #column_offset,
+                }
+            });
+            location += 1;
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics crate::vulkan::vertex::VertexInput for #name #ty_generics #where_clause {
+            const INPUT_RATE: ::ash::vk::VertexInputRate = ::ash::vk::VertexInputRate::#input_rate;
+
+            fn attribute_descriptions(binding: u32) -> ::std::vec::Vec<::ash::vk::VertexInputAttributeDescription> {
+                ::std::vec![#(#attributes),*]
+            }
+        }
+    })
+}
+
+fn is_repr_c(attrs: &[Attribute]) -> syn::Result<bool> {
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        let reprs = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+        if reprs.iter().any(|repr| repr.path().is_ident("C")) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn name_value<'a>(field: &'a Field, name: &str) -> syn::Result<Option<&'a Lit>> {
+    let Some(attr) = field.attrs.iter().find(|attr| attr.path().is_ident(name)) else {
+        return Ok(None);
+    };
+    match &attr.meta {
+        Meta::NameValue(meta) => match &meta.value {
+            Expr::Lit(lit) => Ok(Some(&lit.lit)),
+            value => Err(syn::Error::new(value.span(), format!("expected a literal for #[{} = ...]", name))),
+        },
+        meta => Err(syn::Error::new(meta.span(), format!("expected #[{} = ...]", name))),
+    }
+}
+
+fn location_attr(field: &Field) -> syn::Result<Option<u32>> {
+    match name_value(field, "location")? {
+        Some(Lit::Int(lit)) => Ok(Some(lit.base10_parse()?)),
+        Some(lit) => Err(syn::Error::new(lit.span(), "expected an integer location")),
+        None => Ok(None),
+    }
+}
+
+fn format_attr(field: &Field) -> syn::Result<Option<String>> {
+    match name_value(field, "format")? {
+        Some(Lit::Str(lit)) => Ok(Some(lit.value())),
+        Some(lit) => Err(syn::Error::new(lit.span(), "expected a vk::Format name such as \"R8G8B8A8_UINT\"")),
+        None => Ok(None),
+    }
+}
+
+struct FieldLayout {
+    // name of the vk::Format constant
+    format: String,
+    columns: u32,
+    column_size: u32,
+}
+
+// scalars and arrays of up to 4 scalars take one location,
+// an array of those takes one location per element
+fn field_layout(ty: &Type) -> syn::Result<FieldLayout> {
+    if let Type::Array(outer) = ty {
+        if let Type::Array(inner) = &*outer.elem {
+            let columns = array_len(&outer.len, 1..=4)?;
+            let (format, column_size) = vector_format(&inner.elem, array_len(&inner.len, 1..=4)?)?;
+            return Ok(FieldLayout {
+                format,
+                columns,
+                column_size,
+            });
+        }
+        let (format, column_size) = vector_format(&outer.elem, array_len(&outer.len, 1..=4)?)?;
+        return Ok(FieldLayout {
+            format,
+            columns: 1,
+            column_size,
+        });
+    }
+
+    let (format, column_size) = vector_format(ty, 1)?;
+    Ok(FieldLayout {
+        format,
+        columns: 1,
+        column_size,
+    })
+}
+
+fn array_len(expr: &Expr, range: std::ops::RangeInclusive<u32>) -> syn::Result<u32> {
+    let len = match expr {
+        Expr::Lit(syn::ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse()?,
+        _ => return Err(syn::Error::new(expr.span(), "array length must be an integer literal")),
+    };
+    if !range.contains(&len) {
+        return Err(syn::Error::new(expr.span(), format!("array length must be within {:?}", range)));
+    }
+    Ok(len)
+}
+
+// returns the format name and the size in bytes of `components` scalars of type `scalar`
+fn vector_format(scalar: &Type, components: u32) -> syn::Result<(String, u32)> {
+    let unsupported = || syn::Error::new(
+        scalar.span(),
+        "unsupported vertex attribute type, expected f32, u32, i32, u16, i16, u8 or i8, \
+        optionally in arrays, or a #[format = \"...\"] override",
+    );
+
+    let Type::Path(path) = scalar else {
+        return Err(unsupported());
+    };
+    let Some(ident) = path.path.get_ident() else {
+        return Err(unsupported());
+    };
+
+    // 8 bit integers are normalized as they are mostly colors and packed normals
+    let (bits, numeric) = match ident.to_string().as_str() {
+        "f32" => (32, "SFLOAT"),
+        "u32" => (32, "UINT"),
+        "i32" => (32, "SINT"),
+        "u16" => (16, "UINT"),
+        "i16" => (16, "SINT"),
+        "u8" => (8, "UNORM"),
+        "i8" => (8, "SNORM"),
+        _ => return Err(unsupported()),
+    };
+
+    let channels: String = ["R", "G", "B", "A"][..components as usize]
+        .iter()
+        .map(|channel| format!("{}{}", channel, bits))
+        .collect();
+
+    Ok((format!("{}_{}", channels, numeric), components * bits / 8))
+}
@@ -0,0 +1,107 @@
+use std::fmt::Write;
+
+#[path = "src/vulkan/shader_compile.rs"]
+mod shader_compile;
+
+use shader_compile::{CompileSettings, Defines};
+
+// compiles every shader in src/shaders to SPIR-V embedded through src/vulkan/shaders.rs,
+// any compile error fails the build with the shaderc diagnostics
+//
+// a shader is compiled once without defines and once per line such as
+//     // permutation: ALPHA_TEST LIGHT_COUNT=4
+// in its source, pipelines pick one with GraphicsPipelineBuilder::define,
+// other define sets are compiled at runtime, see ShaderCompiler::spirv
+fn main() {
+    let shaders_dir = shader_compile::shaders_dir();
+    // covers included files as well
+    println!("cargo:rerun-if-changed={}", shaders_dir.display());
+
+    let mut file_names: Vec<_> = std::fs::read_dir(shaders_dir)
+        .unwrap_or_else(|err| panic!("could not read {}: {}", shaders_dir.display(), err))
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|file_name| shader_compile::shader_kind(file_name).is_some())
+        .collect();
+    // stable output across file systems
+    file_names.sort();
+
+    let compiler = shaderc::Compiler::new().expect("could not initialize shaderc");
+    let settings = settings_for_profile();
+
+    let mut generated = String::new();
+    let mut failed = false;
+    for file_name in &file_names {
+        let source = std::fs::read_to_string(shaders_dir.join(file_name))
+            .unwrap_or_else(|err| panic!("could not read {}: {}", file_name, err));
+
+        let mut permutations = String::new();
+        for defines in std::iter::once(Defines::new()).chain(declared_permutations(&source)) {
+            let artifact = match shader_compile::compile(&compiler, file_name, &defines, settings) {
+                Ok(artifact) => artifact,
+                Err(diagnostics) => {
+                    eprintln!("{}", diagnostics);
+                    failed = true;
+                    continue;
+                }
+            };
+            for warning in artifact.get_warning_messages().lines() {
+                println!("cargo:warning={}", warning);
+            }
+
+            let defines: Vec<_> = defines.iter().map(|(name, value)| format!("({:?}, {:?})", name, value.as_deref())).collect();
+            let words: Vec<_> = artifact.as_binary().iter().map(|word| format!("{:#010x}", word)).collect();
+            writeln!(
+                permutations,
+                "    Permutation {{ defines: &[{}], spirv: &[{}] }},",
+                defines.join(", "),
+                words.join(", "),
+            ).unwrap();
+        }
+
+        writeln!(
+            generated,
+            "pub const {}: EmbeddedShader = EmbeddedShader {{ file_name: {:?}, permutations: &[\n{}] }};",
+            file_name.replace('.', "_").to_uppercase(),
+            file_name,
+            permutations,
+        ).unwrap();
+    }
+
+    if failed {
+        eprintln!("shader compilation failed");
+        std::process::exit(1);
+    }
+
+    let out_path = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("shaders.rs");
+    std::fs::write(&out_path, generated)
+        .unwrap_or_else(|err| panic!("could not write {}: {}", out_path.display(), err));
+}
+
+// follows the profile the crate itself is built with
+fn settings_for_profile() -> CompileSettings {
+    let optimization = match std::env::var("OPT_LEVEL").as_deref() {
+        Ok("0") => shaderc::OptimizationLevel::Zero,
+        Ok("s" | "z") => shaderc::OptimizationLevel::Size,
+        _ => shaderc::OptimizationLevel::Performance,
+    };
+    let debug_info = !matches!(std::env::var("DEBUG").as_deref(), Ok("false" | "0" | "none") | Err(_));
+    CompileSettings {
+        optimization,
+        debug_info,
+    }
+}
+
+fn declared_permutations(source: &str) -> impl Iterator<Item = Defines> + '_ {
+    source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("// permutation:"))
+        .map(|defines| {
+            defines
+                .split_whitespace()
+                .map(|define| match define.split_once('=') {
+                    Some((name, value)) => (name.to_owned(), Some(value.to_owned())),
+                    None => (define.to_owned(), None),
+                })
+                .collect()
+        })
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+// assets are named by forward slash separated paths relative to the asset root, e.g. "textures/apple.png",
+// and are read from loose files or from a packed archive made with pack()
+
+const ARCHIVE_MAGIC: &[u8; 8] = b"ASHPAK\0\x01";
+const ARCHIVE_EXTENSION: &str = "pak";
+
+#[derive(Debug)]
+pub enum AssetError {
+    Missing {
+        name: String,
+        searched: Vec<PathBuf>,
+    },
+    InvalidArchive {
+        path: PathBuf,
+        reason: String,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing { name, searched } => {
+                write!(f, "asset {} not found, searched", name)?;
+                if searched.is_empty() {
+                    write!(f, " nowhere as no asset root was found")?;
+                }
+                for path in searched {
+                    write!(f, "\n    {}", path.display())?;
+                }
+                Ok(())
+            }
+            Self::InvalidArchive { path, reason } => write!(f, "invalid asset archive {}: {}", path.display(), reason),
+            Self::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> AssetError + '_ {
+    move |source| AssetError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Source {
+    Directory(PathBuf),
+    Archive {
+        path: PathBuf,
+        // name to offset and size
+        entries: HashMap<String, (u64, u64)>,
+    },
+}
+
+// searched in order, so loose files can override a packed archive
+#[derive(Clone, Debug, Default)]
+pub struct Assets {
+    sources: Vec<Source>,
+}
+
+impl Assets {
+    // `root` is a directory of loose files or an archive,
+    // without one, assets and assets.pak next to the executable are used, whichever exist,
+    // and while run through cargo the assets directory of the crate as well
+    pub fn new(root: Option<&Path>) -> Result<Self, AssetError> {
+        let mut assets = Self::default();
+        match root {
+            Some(root) => assets.add(root)?,
+            None => {
+                let mut candidates = Vec::new();
+                if let Some(exe_dir) = std::env::current_exe().ok().as_deref().and_then(Path::parent) {
+                    candidates.push(exe_dir.join("assets"));
+                    candidates.push(exe_dir.join("assets").with_extension(ARCHIVE_EXTENSION));
+                }
+                if let Some(manifest_dir) = std::env::var_os("CARGO_MANIFEST_DIR") {
+                    candidates.push(Path::new(&manifest_dir).join("assets"));
+                }
+                for candidate in candidates.iter().filter(|candidate| candidate.exists()) {
+                    assets.add(candidate)?;
+                }
+            }
+        }
+        Ok(assets)
+    }
+
+    fn add(&mut self, path: &Path) -> Result<(), AssetError> {
+        let metadata = std::fs::metadata(path).map_err(io_error(path))?;
+        let source = if metadata.is_dir() {
+            Source::Directory(path.to_path_buf())
+        } else {
+            Source::Archive {
+                path: path.to_path_buf(),
+                entries: read_archive_index(path)?,
+            }
+        };
+        log::info!("Using assets from {}", path.display());
+        self.sources.push(source);
+        Ok(())
+    }
+
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, AssetError> {
+        for source in &self.sources {
+            match source {
+                Source::Directory(dir) => {
+                    let path = dir.join(name);
+                    match std::fs::read(&path) {
+                        Ok(bytes) => return Ok(bytes),
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                        Err(err) => return Err(io_error(&path)(err)),
+                    }
+                }
+                Source::Archive { path, entries } => {
+                    if let Some(&(offset, size)) = entries.get(name) {
+                        return read_archive_entry(path, offset, size);
+                    }
+                }
+            }
+        }
+
+        Err(AssetError::Missing {
+            name: name.to_owned(),
+            searched: self.sources
+                .iter()
+                .map(|source| match source {
+                    Source::Directory(dir) => dir.join(name),
+                    Source::Archive { path, .. } => path.clone(),
+                })
+                .collect(),
+        })
+    }
+}
+
+// layout, all integers little endian:
+//     magic, entry count: u32
+//     per entry: name length: u32, name, offset from the start of the file: u64, size: u64
+//     entry data
+fn read_archive_index(path: &Path) -> Result<HashMap<String, (u64, u64)>, AssetError> {
+    let invalid = |reason: &str| AssetError::InvalidArchive {
+        path: path.to_path_buf(),
+        reason: reason.to_owned(),
+    };
+
+    let mut file = io::BufReader::new(File::open(path).map_err(io_error(path))?);
+    let file_size = file.get_ref().metadata().map_err(io_error(path))?.len();
+
+    let mut read = |len: usize| -> Result<Vec<u8>, AssetError> {
+        let mut bytes = vec![0; len];
+        file.read_exact(&mut bytes).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => invalid("truncated index"),
+            _ => io_error(path)(err),
+        })?;
+        Ok(bytes)
+    };
+
+    if read(ARCHIVE_MAGIC.len())? != ARCHIVE_MAGIC {
+        return Err(invalid("not an asset archive"));
+    }
+    let entry_count = u32::from_le_bytes(read(4)?.try_into().unwrap());
+
+    let mut entries = HashMap::new();
+    for _ in 0..entry_count {
+        let name_len = u32::from_le_bytes(read(4)?.try_into().unwrap()) as usize;
+        let name = String::from_utf8(read(name_len)?).map_err(|_| invalid("entry name is not UTF-8"))?;
+        let offset = u64::from_le_bytes(read(8)?.try_into().unwrap());
+        let size = u64::from_le_bytes(read(8)?.try_into().unwrap());
+        if offset.checked_add(size).is_none_or(|end| end > file_size) {
+            return Err(invalid(&format!("entry {} lies outside of the file", name)));
+        }
+        entries.insert(name, (offset, size));
+    }
+    Ok(entries)
+}
+
+fn read_archive_entry(path: &Path, offset: u64, size: u64) -> Result<Vec<u8>, AssetError> {
+    let mut file = File::open(path).map_err(io_error(path))?;
+    file.seek(SeekFrom::Start(offset)).map_err(io_error(path))?;
+    let mut bytes = vec![0; size as usize];
+    file.read_exact(&mut bytes).map_err(io_error(path))?;
+    Ok(bytes)
+}
+
+// packs every file below `dir` into an archive at `out_path`, named relative to `dir`
+pub fn pack(dir: &Path, out_path: &Path) -> Result<usize, AssetError> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort();
+
+    let names: Vec<_> = files
+        .iter()
+        .map(|file| {
+            let relative = file.strip_prefix(dir).unwrap();
+            relative.components().map(|component| component.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+        })
+        .collect();
+
+    let index_size: usize = ARCHIVE_MAGIC.len() + 4 + names.iter().map(|name| 4 + name.len() + 8 + 8).sum::<usize>();
+    let mut index = Vec::with_capacity(index_size);
+    index.extend_from_slice(ARCHIVE_MAGIC);
+    index.extend_from_slice(&(files.len() as u32).to_le_bytes());
+
+    let mut offset = index_size as u64;
+    let mut sizes = Vec::with_capacity(files.len());
+    for (file, name) in files.iter().zip(&names) {
+        let size = std::fs::metadata(file).map_err(io_error(file))?.len();
+        index.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        index.extend_from_slice(name.as_bytes());
+        index.extend_from_slice(&offset.to_le_bytes());
+        index.extend_from_slice(&size.to_le_bytes());
+        offset += size;
+        sizes.push(size);
+    }
+
+    let mut out = io::BufWriter::new(File::create(out_path).map_err(io_error(out_path))?);
+    out.write_all(&index).map_err(io_error(out_path))?;
+    for (file, size) in files.iter().zip(sizes) {
+        let mut input = File::open(file).map_err(io_error(file))?;
+        // a file that changed size since being indexed would corrupt the archive
+        let copied = io::copy(&mut input, &mut out).map_err(io_error(file))?;
+        if copied != size {
+            return Err(AssetError::Io {
+                path: file.clone(),
+                source: io::Error::other("file changed while packing"),
+            });
+        }
+    }
+    out.flush().map_err(io_error(out_path))?;
+
+    Ok(files.len())
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), AssetError> {
+    for entry in std::fs::read_dir(dir).map_err(io_error(dir))? {
+        let path = entry.map_err(io_error(dir))?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ash_learn_assets_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn packed_and_loose_assets() {
+        let dir = temp_dir("pack");
+        let loose = dir.join("loose");
+        std::fs::create_dir_all(loose.join("textures")).unwrap();
+        std::fs::write(loose.join("textures/apple.png"), b"apple").unwrap();
+        std::fs::write(loose.join("readme.txt"), b"").unwrap();
+
+        let archive = dir.join("assets.pak");
+        assert_eq!(pack(&loose, &archive).unwrap(), 2);
+
+        let packed = Assets::new(Some(&archive)).unwrap();
+        assert_eq!(packed.read("textures/apple.png").unwrap(), b"apple");
+        assert_eq!(packed.read("readme.txt").unwrap(), b"");
+
+        // loose files are searched first
+        let mut assets = Assets::new(Some(&loose)).unwrap();
+        assets.add(&archive).unwrap();
+        std::fs::write(loose.join("textures/apple.png"), b"pear").unwrap();
+        assert_eq!(assets.read("textures/apple.png").unwrap(), b"pear");
+
+        match assets.read("textures/missing.png") {
+            Err(AssetError::Missing { name, searched }) => {
+                assert_eq!(name, "textures/missing.png");
+                assert_eq!(searched, [loose.join("textures/missing.png"), archive]);
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files_as_archives() {
+        let dir = temp_dir("invalid");
+        let path = dir.join("assets.pak");
+        std::fs::write(&path, b"PK\x03\x04 not ours").unwrap();
+        assert!(matches!(Assets::new(Some(&path)), Err(AssetError::InvalidArchive { .. })));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
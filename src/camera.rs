@@ -57,4 +57,32 @@ impl Camera {
             near_z: self.near_z,
         }       
     }
+}
+
+impl CameraRender {
+    // world space planes (normal, distance) with the visible side positive:
+    // left, right, bottom, top and near, there is no far plane with reversed z
+    pub fn frustum_planes(&self) -> [[f32; 4]; 5] {
+        // main.vert divides x and y by the view space z and puts near_z / z into depth,
+        // so a point is visible when |x| <= z, |y| <= z and z >= near_z
+        let view_planes = [
+            [1.0, 0.0, 1.0, 0.0],
+            [-1.0, 0.0, 1.0, 0.0],
+            [0.0, 1.0, 1.0, 0.0],
+            [0.0, -1.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0, -self.near_z],
+        ];
+        let v = &self.view;
+        view_planes.map(|[x, y, z, d]| {
+            // n . (view * p) + d = (n * view) . p + (n . translation + d)
+            let normal = Vector3::new(
+                x * v.xx + y * v.xy + z * v.xz,
+                x * v.yx + y * v.yy + z * v.yz,
+                x * v.zx + y * v.zy + z * v.zz,
+            );
+            let distance = x * v._x + y * v._y + z * v._z + d;
+            let norm = normal.norm_sqr().sqrt();
+            [normal.x / norm, normal.y / norm, normal.z / norm, distance / norm]
+        })
+    }
 }
\ No newline at end of file
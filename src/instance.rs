@@ -1,3 +1,52 @@
-struct Instance {
-    
-}
\ No newline at end of file
+use crate::{math::Affine3, vulkan::vertex::VertexInput};
+
+// per instance data of Vulkan::draw_instanced, bound after the mesh vertices
+#[derive(VertexInput, Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+#[instance]
+pub struct Instance {
+    // the rows of an Affine3 model transform, as the derive does not know Affine3
+    #[location = 4]
+    pub transform: [[f32; 4]; 3],
+    // multiplies the vertex color
+    pub tint: [u8; 4],
+    // layer of the texture array passed to draw_instanced
+    pub texture_layer: u32,
+}
+
+impl Instance {
+    pub fn new(transform: &Affine3, tint: [u8; 4], texture_layer: u32) -> Self {
+        Self {
+            transform: bytemuck::cast(*transform),
+            tint,
+            texture_layer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use super::*;
+
+    #[test]
+    fn instance_follows_main_vert() {
+        let locations: Vec<_> = Instance::attribute_descriptions(1)
+            .iter()
+            .map(|desc| (desc.location, desc.format, desc.offset))
+            .collect();
+        assert_eq!(locations, [
+            (4, vk::Format::R32G32B32A32_SFLOAT, 0),
+            (5, vk::Format::R32G32B32A32_SFLOAT, 16),
+            (6, vk::Format::R32G32B32A32_SFLOAT, 32),
+            (7, vk::Format::R8G8B8A8_UNORM, 48),
+            (8, vk::Format::R32_UINT, 52),
+        ]);
+
+        let transform = Affine3::IDENTITY.translate(&crate::math::Vector3::new(1.0, 2.0, 3.0));
+        let instance = Instance::new(&transform, [255; 4], 0);
+        assert_eq!(instance.transform[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(instance.transform[2], [0.0, 0.0, 1.0, 3.0]);
+    }
+}
@@ -1,5 +1,7 @@
-use std::time::{Duration, Instant};
+use std::{path::PathBuf, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+mod assets;
 mod input;
+mod mesh;
 mod vulkan;
 mod math;
 mod camera;
@@ -11,9 +13,39 @@ pub const TARGET_DT: f32 = 1.0 / TARGET_FPS as f32;
 use ash::vk;
 use math::Vector3;
 use winit::{dpi::PhysicalSize, event::{Event, KeyEvent, WindowEvent}, event_loop::{ControlFlow, EventLoop}, *};
+
+const USAGE: &str = "usage: ash_learn [--assets <dir or archive>] [--mesh <asset>]... [--instances <count>] | --pack-assets <dir> <archive>";
+
 fn main() {
     env_logger::init();
 
+    let mut config = vulkan::Config::from_env();
+    let mut mesh_names = Vec::new();
+    let mut instance_count = 0;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--assets", Some(root)) => config.asset_root = Some(PathBuf::from(root)),
+            ("--mesh", Some(name)) => mesh_names.push(name),
+            ("--instances", Some(count)) if count.parse::<u32>().is_ok() => instance_count = count.parse().unwrap(),
+            ("--pack-assets", Some(dir)) => {
+                let Some(out_path) = args.next() else {
+                    eprintln!("{}", USAGE);
+                    return;
+                };
+                match assets::pack(dir.as_ref(), out_path.as_ref()) {
+                    Ok(count) => println!("Packed {} files into {}", count, out_path),
+                    Err(err) => eprintln!("Could not pack assets: {}", err),
+                }
+                return;
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                return;
+            }
+        }
+    }
+
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
 
@@ -25,7 +57,22 @@ fn main() {
         .build(&event_loop)
         .unwrap();
 
-    let mut vulkan = vulkan::Vulkan::new(&window);
+    let mut vulkan = match vulkan::Vulkan::new(&window, &config) {
+        Ok(vulkan) => vulkan,
+        Err(err) => {
+            log::error!("Could not initialize renderer: {}", err);
+            return;
+        }
+    };
+
+    let first_mesh = match add_meshes(&mut vulkan, &config, &mesh_names) {
+        Ok(first_mesh) => first_mesh,
+        Err(err) => {
+            log::error!("Could not load meshes: {}", err);
+            return;
+        }
+    };
+    let instances = instance_grid(instance_count);
 
     let mut input_state = input::InputState::new();
 
@@ -34,7 +81,7 @@ fn main() {
     let mut camera = camera::Camera::new(
         Vector3::new(0.0, 0.0, -1.0),
         3.0,
-        3.0 * vulkan.swapchain_extent.height as f32 / vulkan.swapchain_extent.width as f32,
+        3.0 * vulkan.extent.height as f32 / vulkan.extent.width as f32,
         0.1,
         2.0,
         2.0,
@@ -52,34 +99,56 @@ fn main() {
                 WindowEvent::Resized(PhysicalSize{width, height}) => {
                     camera.height = camera.width * height as f32 / width as f32;
 
-                    vulkan.swapchain_extent = vk::Extent2D {
+                    vulkan.extent = vk::Extent2D {
                         width,
                         height,
                     };
 
                     if width != 0 && height != 0 {
-                        vulkan.renew_swapchain();
+                        if let Err(err) = vulkan.renew_swapchain() {
+                            log::error!("Error renewing swapchain: {}", err);
+                            elwt.exit();
+                        }
                     }
                 }
                 WindowEvent::KeyboardInput {
                     event: KeyEvent {
                         physical_key: PhysicalKey::Code(key_code),
                         state,
+                        repeat,
                         ..
                     },
                     ..
                 } => {
                     if key_code == KeyCode::Escape {
                         elwt.exit();
+                    } else if key_code == KeyCode::F12 {
+                        if state.is_pressed() && !repeat {
+                            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+                            if let Err(err) = vulkan.capture_screenshot(format!("screenshot_{timestamp}.png")) {
+                                log::error!("Error capturing screenshot: {}", err);
+                            }
+                        }
+                    } else if key_code == KeyCode::F9 {
+                        // freezes the culling frustum to fly around what was culled
+                        if state.is_pressed() && !repeat {
+                            let frozen = !vulkan.culling_frozen();
+                            vulkan.freeze_culling(frozen);
+                            log::info!("Culling frustum {}", if frozen { "frozen" } else { "unfrozen" });
+                        }
                     } else {
                         input_state.set_key_pressed(key_code, state.is_pressed());
                     }
                 },
                 _ => {}
             }
-            Event::AboutToWait if vulkan.swapchain_extent.width != 0 && vulkan.swapchain_extent.height != 0 => {
+            Event::AboutToWait if vulkan.extent.width != 0 && vulkan.extent.height != 0 => {
                 vulkan.update_camera(&camera);
-                vulkan.draw_frame();
+                vulkan.draw_instanced(first_mesh, vulkan::texture::TextureId::DEFAULT, &instances);
+                if let Err(err) = vulkan.draw_frame() {
+                    log::error!("Error drawing frame: {}", err);
+                    elwt.exit();
+                }
 
                 // framerate
                 {
@@ -141,4 +210,50 @@ fn main() {
             _ => ()
         }
     });
-}
\ No newline at end of file
+}
+
+// adds an object per mesh, or the tetrahedron when none are given, and returns the first mesh
+fn add_meshes(vulkan: &mut vulkan::Vulkan, config: &vulkan::Config, names: &[String]) -> Result<vulkan::mesh::MeshId, Box<dyn std::error::Error>> {
+    let add = |vulkan: &mut vulkan::Vulkan, mesh: &mesh::MeshData| {
+        let mesh = vulkan.upload_mesh(mesh);
+        vulkan.scene.add(vulkan::scene::Object {
+            mesh,
+            texture: vulkan::texture::TextureId::DEFAULT,
+            transform: math::Affine3::IDENTITY,
+        });
+        mesh
+    };
+
+    if names.is_empty() {
+        return Ok(add(vulkan, &mesh::MeshData::tetrahedron()));
+    }
+
+    let assets = assets::Assets::new(config.asset_root.as_deref())?;
+    let mut first_mesh = None;
+    for name in names {
+        let meshes = mesh::load(&assets, name).map_err(|err| format!("{}: {}", name, err))?;
+        for mesh in &meshes {
+            let mesh = add(vulkan, mesh);
+            first_mesh.get_or_insert(mesh);
+        }
+        log::info!("Loaded {} meshes from {}", meshes.len(), name);
+    }
+    Ok(first_mesh.ok_or("no meshes found")?)
+}
+
+// copies of the first mesh on a square grid in the xz plane behind the scene, tinted by position
+fn instance_grid(count: u32) -> Vec<instance::Instance> {
+    let side = (count as f32).sqrt().ceil().max(1.0) as u32;
+    (0..count)
+        .map(|i| {
+            let (x, z) = (i % side, i / side);
+            let transform = math::Affine3::IDENTITY.translate(&Vector3::new(
+                3.0 * (x as f32 - side as f32 / 2.0),
+                0.0,
+                3.0 * (z as f32 + 2.0),
+            ));
+            let tint = [(255 * x / side) as u8, 255, (255 * z / side) as u8, 255];
+            instance::Instance::new(&transform, tint, 0)
+        })
+        .collect()
+}
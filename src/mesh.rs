@@ -0,0 +1,430 @@
+use std::{fmt, path::Path};
+
+use crate::{
+    assets::{AssetError, Assets},
+    vulkan::vertex::{Index, Vertex},
+};
+
+// triangle lists as uploaded to the gpu, missing attributes are filled with
+// white for colors, zero for texture coordinates and face normals for normals
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<Index>,
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    Asset(AssetError),
+    Obj(tobj::LoadError),
+    Gltf(gltf::Error),
+    Unsupported(String),
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Asset(err) => write!(f, "{}", err),
+            Self::Obj(err) => write!(f, "invalid OBJ: {}", err),
+            Self::Gltf(err) => write!(f, "invalid glTF: {}", err),
+            Self::Unsupported(what) => write!(f, "unsupported mesh: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Asset(err) => Some(err),
+            Self::Obj(err) => Some(err),
+            Self::Gltf(err) => Some(err),
+            Self::Unsupported(_) => None,
+        }
+    }
+}
+
+impl From<AssetError> for MeshError {
+    fn from(value: AssetError) -> Self {
+        Self::Asset(value)
+    }
+}
+
+impl From<tobj::LoadError> for MeshError {
+    fn from(value: tobj::LoadError) -> Self {
+        Self::Obj(value)
+    }
+}
+
+impl From<gltf::Error> for MeshError {
+    fn from(value: gltf::Error) -> Self {
+        Self::Gltf(value)
+    }
+}
+
+// one mesh per OBJ object or glTF primitive, picked by the extension of `name`
+pub fn load(assets: &Assets, name: &str) -> Result<Vec<MeshData>, MeshError> {
+    match Path::new(name).extension().and_then(|extension| extension.to_str()) {
+        Some("obj") => parse_obj(&assets.read(name)?),
+        Some("gltf" | "glb") => {
+            // external buffers are named relative to the glTF file
+            let dir = name.rsplit_once('/').map_or("", |(dir, _)| dir);
+            parse_gltf(&assets.read(name)?, |uri| {
+                let name = if dir.is_empty() { uri.to_owned() } else { format!("{}/{}", dir, uri) };
+                Ok(assets.read(&name)?)
+            })
+        }
+        _ => Err(MeshError::Unsupported(format!("{} is neither OBJ nor glTF", name))),
+    }
+}
+
+// materials are not loaded, so material libraries are ignored
+pub fn parse_obj(bytes: &[u8]) -> Result<Vec<MeshData>, MeshError> {
+    let (models, _) = tobj::load_obj_buf(
+        &mut std::io::Cursor::new(bytes),
+        &tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ignore_points: true,
+            ignore_lines: true,
+        },
+        |_| Err(tobj::LoadError::OpenFileFailed),
+    )?;
+
+    Ok(models
+        .into_iter()
+        .map(|model| {
+            let mesh = model.mesh;
+            let vertex_count = mesh.positions.len() / 3;
+            let vertices = (0..vertex_count)
+                .map(|i| Vertex {
+                    position: [mesh.positions[3 * i], mesh.positions[3 * i + 1], mesh.positions[3 * i + 2]],
+                    color: mesh.vertex_color.get(3 * i..3 * i + 3).map_or([1.0; 3], |c| [c[0], c[1], c[2]]),
+                    // OBJ puts v = 0 at the bottom of the image, vulkan at the top
+                    tex_coord: mesh.texcoords.get(2 * i..2 * i + 2).map_or([0.0; 2], |t| [t[0], 1.0 - t[1]]),
+                    normal: mesh.normals.get(3 * i..3 * i + 3).map_or([0.0; 3], |n| [n[0], n[1], n[2]]),
+                })
+                .collect();
+
+            let mut mesh_data = MeshData {
+                vertices,
+                indices: mesh.indices,
+            };
+            if mesh.normals.is_empty() {
+                mesh_data.compute_normals();
+            }
+            mesh_data
+        })
+        .collect())
+}
+
+// takes the meshes of the default scene, or of the first one, with their node transforms applied,
+// `read_external` reads buffers that are neither embedded data URIs nor the .glb binary chunk
+pub fn parse_gltf(
+    bytes: &[u8],
+    mut read_external: impl FnMut(&str) -> Result<Vec<u8>, MeshError>,
+) -> Result<Vec<MeshData>, MeshError> {
+    let gltf::Gltf { document, mut blob } = gltf::Gltf::from_slice(bytes)?;
+
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:") => read_external(uri)?,
+            source => gltf::buffer::Data::from_source_and_blob(source, None, &mut blob)?.0,
+        };
+        if data.len() < buffer.length() {
+            return Err(MeshError::Gltf(gltf::Error::BufferLength {
+                buffer: buffer.index(),
+                expected: buffer.length(),
+                actual: data.len(),
+            }));
+        }
+        buffers.push(data);
+    }
+
+    let mut meshes = Vec::new();
+    match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => {
+            for node in scene.nodes() {
+                read_gltf_node(&node, &IDENTITY, &buffers, &mut meshes)?;
+            }
+        }
+        // without scenes the meshes are taken as they are
+        None => {
+            for mesh in document.meshes() {
+                read_gltf_mesh(&mesh, &IDENTITY, &buffers, &mut meshes)?;
+            }
+        }
+    }
+    Ok(meshes)
+}
+
+fn read_gltf_node(
+    node: &gltf::Node,
+    parent_transform: &Matrix4,
+    buffers: &[Vec<u8>],
+    meshes: &mut Vec<MeshData>,
+) -> Result<(), MeshError> {
+    let transform = multiply(parent_transform, &node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        read_gltf_mesh(&mesh, &transform, buffers, meshes)?;
+    }
+    for child in node.children() {
+        read_gltf_node(&child, &transform, buffers, meshes)?;
+    }
+    Ok(())
+}
+
+// column major, as in glTF
+type Matrix4 = [[f32; 4]; 4];
+
+const IDENTITY: Matrix4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+    let mut product = [[0.0; 4]; 4];
+    for (column, b_column) in product.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    product
+}
+
+fn transform_point(m: &Matrix4, [x, y, z]: [f32; 3]) -> [f32; 3] {
+    std::array::from_fn(|row| m[0][row] * x + m[1][row] * y + m[2][row] * z + m[3][row])
+}
+
+fn transform_direction(m: &Matrix4, [x, y, z]: [f32; 3]) -> [f32; 3] {
+    std::array::from_fn(|row| m[0][row] * x + m[1][row] * y + m[2][row] * z)
+}
+
+fn read_gltf_mesh(
+    mesh: &gltf::Mesh,
+    transform: &Matrix4,
+    buffers: &[Vec<u8>],
+    meshes: &mut Vec<MeshData>,
+) -> Result<(), MeshError> {
+    let normal_transform = normal_matrix(transform);
+
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            return Err(MeshError::Unsupported(format!("{:?} in mesh {}", primitive.mode(), mesh.index())));
+        }
+
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let Some(positions) = reader.read_positions() else {
+            return Err(MeshError::Unsupported(format!("primitive without positions in mesh {}", mesh.index())));
+        };
+
+        let mut vertices: Vec<_> = positions
+            .map(|position| Vertex {
+                position: transform_point(transform, position),
+                color: [1.0; 3],
+                tex_coord: [0.0; 2],
+                normal: [0.0; 3],
+            })
+            .collect();
+        if let Some(normals) = reader.read_normals() {
+            for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                vertex.normal = normalize(transform_direction(&normal_transform, normal));
+            }
+        }
+        if let Some(tex_coords) = reader.read_tex_coords(0) {
+            for (vertex, tex_coord) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                vertex.tex_coord = tex_coord;
+            }
+        }
+        if let Some(colors) = reader.read_colors(0) {
+            for (vertex, color) in vertices.iter_mut().zip(colors.into_rgb_f32()) {
+                vertex.color = color;
+            }
+        }
+
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.len() as Index).collect(),
+        };
+
+        let mut mesh_data = MeshData { vertices, indices };
+        if reader.read_normals().is_none() {
+            mesh_data.compute_normals();
+        }
+        meshes.push(mesh_data);
+    }
+    Ok(())
+}
+
+// the inverse transpose of the upper 3x3 up to a positive scale, which normalizing removes,
+// computed as its cofactors, which are the inverse transpose times the determinant
+fn normal_matrix(m: &Matrix4) -> Matrix4 {
+    let c = |i: usize, j: usize| m[i % 3][j % 3];
+    let mut cofactors = IDENTITY;
+    for (i, column) in cofactors.iter_mut().take(3).enumerate() {
+        for (j, value) in column.iter_mut().take(3).enumerate() {
+            *value = c(i + 1, j + 1) * c(i + 2, j + 2) - c(i + 1, j + 2) * c(i + 2, j + 1);
+        }
+    }
+
+    // a mirroring transform has a negative determinant, which would flip the normals
+    let determinant: f32 = (0..3).map(|j| m[0][j] * cofactors[0][j]).sum();
+    if determinant < 0.0 {
+        for column in cofactors.iter_mut().take(3) {
+            for value in column.iter_mut().take(3) {
+                *value = -*value;
+            }
+        }
+    }
+    cofactors
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length > 0.0 {
+        v.map(|x| x / length)
+    } else {
+        v
+    }
+}
+
+impl MeshData {
+    // drawn when no other meshes are given
+    pub fn tetrahedron() -> Self {
+        let vertex = |position, color, tex_coord| Vertex {
+            position,
+            color,
+            tex_coord,
+            normal: [0.0; 3],
+        };
+        let mut tetrahedron = Self {
+            vertices: vec![
+                vertex([-0.5, 1.0, 0.0], [0.8, 1.0, 1.0], [0.0, 1.0]),
+                vertex([1.0, 1.0, -1.0], [0.0, 1.0, 0.0], [1.0, 1.0]),
+                vertex([0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0]),
+                vertex([1.0, 1.0, 1.0], [1.0, 1.0, 1.0], [0.0, 0.0]),
+            ],
+            indices: vec![
+                0, 1, 2,
+                2, 3, 0,
+                1, 3, 2,
+            ],
+        };
+        tetrahedron.compute_normals();
+        tetrahedron
+    }
+
+    // smooth normals, weighted by the area of the triangles sharing a vertex
+    pub fn compute_normals(&mut self) {
+        for vertex in &mut self.vertices {
+            vertex.normal = [0.0; 3];
+        }
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| self.vertices[triangle[i] as usize].position);
+            let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let cross = [
+                ab[1] * ac[2] - ab[2] * ac[1],
+                ab[2] * ac[0] - ab[0] * ac[2],
+                ab[0] * ac[1] - ab[1] * ac[0],
+            ];
+            for &index in triangle {
+                let normal = &mut self.vertices[index as usize].normal;
+                for (n, c) in normal.iter_mut().zip(cross) {
+                    *n += c;
+                }
+            }
+        }
+        for vertex in &mut self.vertices {
+            vertex.normal = normalize(vertex.normal);
+        }
+    }
+
+    // center and radius, centered on the bounding box, all zero without vertices
+    pub fn bounding_sphere(&self) -> [f32; 4] {
+        if self.vertices.is_empty() {
+            return [0.0; 4];
+        }
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for vertex in &self.vertices {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(vertex.position[axis]);
+                max[axis] = max[axis].max(vertex.position[axis]);
+            }
+        }
+        let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+        let radius = self.vertices
+            .iter()
+            .map(|vertex| {
+                let [x, y, z] = [0, 1, 2].map(|axis| vertex.position[axis] - center[axis]);
+                (x * x + y * y + z * z).sqrt()
+            })
+            .fold(0.0, f32::max);
+        [center[0], center[1], center[2], radius]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)).unwrap()
+    }
+
+    #[test]
+    fn obj_objects_become_meshes() {
+        let meshes = parse_obj(&fixture("quad.obj")).unwrap();
+        assert_eq!(meshes.len(), 2);
+
+        let quad = &meshes[0];
+        assert_eq!(quad.vertices.len(), 4);
+        // the quad face is split into two triangles
+        assert_eq!(quad.indices.len(), 6);
+        assert_eq!(quad.vertices[0].position, [-1.0, -1.0, 0.0]);
+        assert_eq!(quad.vertices[0].color, [1.0, 0.0, 0.0]);
+        assert_eq!(quad.vertices[0].tex_coord, [0.0, 1.0]);
+        assert_eq!(quad.vertices[0].normal, [0.0, 0.0, 1.0]);
+
+        // no texture coordinates or normals
+        let triangle = &meshes[1];
+        assert_eq!(triangle.indices, [0, 1, 2]);
+        assert_eq!(triangle.vertices[0].color, [1.0; 3]);
+        assert_eq!(triangle.vertices[0].tex_coord, [0.0; 2]);
+        assert_eq!(triangle.vertices[0].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn gltf_primitives_become_meshes() {
+        let meshes = parse_gltf(&fixture("triangles.gltf"), |uri| {
+            assert_eq!(uri, "triangles.bin");
+            Ok(fixture(uri))
+        })
+        .unwrap();
+        assert_eq!(meshes.len(), 2);
+
+        // indexed, with normals, texture coordinates and colors, translated by its node
+        let indexed = &meshes[0];
+        assert_eq!(indexed.indices, [0, 1, 2]);
+        assert_eq!(indexed.vertices[1].position, [2.0, 0.0, 0.0]);
+        assert_eq!(indexed.vertices[1].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(indexed.vertices[1].tex_coord, [1.0, 0.0]);
+        assert_eq!(indexed.vertices[1].color, [0.0, 1.0, 0.0]);
+
+        // not indexed and only positions, in the same mesh so under the same node
+        let plain = &meshes[1];
+        assert_eq!(plain.indices, [0, 1, 2]);
+        assert_eq!(plain.vertices[1].position, [2.0, 0.0, 0.0]);
+        assert_eq!(plain.vertices[1].color, [1.0; 3]);
+        assert_eq!(plain.vertices[1].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn unsupported_extensions_are_rejected() {
+        assert!(matches!(load(&Assets::default(), "mesh.fbx"), Err(MeshError::Unsupported(_))));
+        assert!(matches!(parse_gltf(b"{}", |_| unreachable!()), Err(MeshError::Gltf(_))));
+    }
+}
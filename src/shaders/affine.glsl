@@ -0,0 +1,18 @@
+#ifndef AFFINE_GLSL
+#define AFFINE_GLSL
+
+// rows of a 3x4 affine transform, the translation in w
+vec3 apply_affine(
+    vec3 pos,
+    vec4 affine_0,
+    vec4 affine_1,
+    vec4 affine_2
+) {
+    return vec3(
+        dot(pos, affine_0.xyz) + affine_0.w,
+        dot(pos, affine_1.xyz) + affine_1.w,
+        dot(pos, affine_2.xyz) + affine_2.w
+    );
+}
+
+#endif
@@ -1,12 +1,15 @@
 #version 450
 
+// permutation: INSTANCED
+
 layout(location = 0) in vec3 f_color;
 layout(location = 1) in vec2 f_tex_coord;
+layout(location = 2) flat in uint f_texture_layer;
 
 layout(location = 0) out vec4 color;
 
-layout(set = 0, binding = 1) uniform sampler2D u_sampler;
+layout(set = 0, binding = 1) uniform sampler2DArray u_sampler;
 
 void main() {
-    color = vec4(f_color * texture(u_sampler, f_tex_coord).rgb, 1.0);
-}
\ No newline at end of file
+    color = vec4(f_color * texture(u_sampler, vec3(f_tex_coord, f_texture_layer)).rgb, 1.0);
+}
@@ -1,9 +1,22 @@
 #version 450
 
+// permutation: INSTANCED
+
+#include "affine.glsl"
+
 layout(location = 0) in vec3 v_position;
 layout(location = 1) in vec3 v_color;
 layout(location = 2) in vec2 v_tex_coord;
 
+#ifdef INSTANCED
+// see instance::Instance
+layout(location = 4) in vec4 i_transform_0;
+layout(location = 5) in vec4 i_transform_1;
+layout(location = 6) in vec4 i_transform_2;
+layout(location = 7) in vec4 i_tint;
+layout(location = 8) in uint i_texture_layer;
+#endif
+
 layout(set = 0, binding = 0) uniform UBO {
     vec4 _0;
     vec4 _1;
@@ -11,30 +24,45 @@ layout(set = 0, binding = 0) uniform UBO {
     float near_z;
 } u_view;
 
+#ifndef INSTANCED
+// model to world space transform of the object being drawn
+layout(push_constant) uniform Model {
+    vec4 _0;
+    vec4 _1;
+    vec4 _2;
+} u_model;
+#endif
+
 layout(location = 0) out vec3 color;
 layout(location = 1) out vec2 tex_coord;
-
-vec3 apply_affine(
-    vec3 pos,
-    vec4 affine_0,
-    vec4 affine_1,
-    vec4 affine_2
-) {
-    return vec3(
-        dot(pos, affine_0.xyz) + affine_0.w,
-        dot(pos, affine_1.xyz) + affine_1.w,
-        dot(pos, affine_2.xyz) + affine_2.w
-    );
-}
+layout(location = 2) flat out uint texture_layer;
 
 void main() {
-    vec3 view_space_pos = apply_affine(
+#ifdef INSTANCED
+    vec3 world_pos = apply_affine(
         v_position,
+        i_transform_0,
+        i_transform_1,
+        i_transform_2
+    );
+    color = v_color * i_tint.rgb;
+    texture_layer = i_texture_layer;
+#else
+    vec3 world_pos = apply_affine(
+        v_position,
+        u_model._0,
+        u_model._1,
+        u_model._2
+    );
+    color = v_color;
+    texture_layer = 0;
+#endif
+    vec3 view_space_pos = apply_affine(
+        world_pos,
         u_view._0,
         u_view._1,
         u_view._2
     );
     gl_Position = vec4(view_space_pos.xy, u_view.near_z, view_space_pos.z);
     tex_coord = v_tex_coord;
-    color = v_color;
-}
\ No newline at end of file
+}
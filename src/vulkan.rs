@@ -1,726 +1,971 @@
-use std::{ffi::c_void, io::BufReader, mem::size_of, ptr::{null, null_mut}};
+use std::{ffi::c_void, io::BufReader, mem::size_of, path::{Path, PathBuf}, ptr::{null, null_mut}};
 
 use ash::{extensions::{ext::DebugUtils, khr::{Surface, Swapchain}}, vk::{self, DebugUtilsMessengerEXT, Extent2D, SurfaceKHR}};
-use image::EncodableLayout;
+use winit::raw_window_handle::HasDisplayHandle;
 
-use super::camera;
+use super::{assets::Assets, camera, instance::Instance, mesh::MeshData};
 const FRAMES_IN_FLIGHT: u8 = 2;
+// each texture has its own descriptor set
+const MAX_TEXTURES: u32 = 256;
 
 mod init;
 pub mod swapchain;
+pub mod offscreen;
 pub mod img;
 pub mod device;
 pub mod render_pass;
 pub mod pipeline;
+pub mod pipeline_cache;
+pub mod reflect;
+pub mod shaders;
+pub mod shader_compile;
+#[cfg(feature = "hot-reload")]
+pub mod shader_watcher;
+pub mod vertex;
+pub mod mesh;
+pub mod texture;
+pub mod scene;
+mod instancing;
+mod indirect;
+mod culling;
+mod async_compute;
 pub mod buffer;
+pub mod upload;
+pub mod command;
+pub mod barrier;
+pub mod readback;
+pub mod allocator;
+pub mod resource;
+mod error;
+mod config;
+
+pub use error::RendererError;
+pub use config::Config;
+
+#[cfg(test)]
+mod golden;
+
+enum RenderTarget {
+    Swapchain(swapchain::SwapchainTarget),
+    Offscreen(offscreen::OffscreenTarget),
+}
 
-pub struct Vulkan {
+#[cfg(feature = "hot-reload")]
+struct HotReload {
+    pipeline_builder: pipeline::GraphicsPipelineBuilder,
+    instanced_pipeline_builder: pipeline::GraphicsPipelineBuilder,
+    cull_pipeline_builder: pipeline::ComputePipelineBuilder,
+    shader_watcher: shader_watcher::ShaderWatcher,
+}
+
+// owns what has to be destroyed after every other resource,
+// dropping the resources only queues their handles for deletion
+struct Context {
     instance: ash::Instance, 
     debug_utils: DebugUtils,
     debug_messenger: DebugUtilsMessengerEXT,
 
-    surface: Surface,
-    surface_khr: SurfaceKHR,
-    surface_format: vk::SurfaceFormatKHR,
+    device: ash::Device,
+    allocator: allocator::Allocator,
+    deletion_queue: resource::DeletionQueue,
+    // saved by Vulkan::drop before it is destroyed here
+    pipeline_cache: vk::PipelineCache,
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe {
+            // the device is idle by now, see Vulkan::drop
+            self.deletion_queue.flush(&self.device, &mut self.allocator);
+            self.allocator.destroy(&self.device);
+            self.device.destroy_pipeline_cache(self.pipeline_cache, None);
+
+            self.device.destroy_device(None);
+
+            self.debug_utils.destroy_debug_utils_messenger(self.debug_messenger, None);
+
+            self.instance.destroy_instance(None);
+        }
+    }
+}
 
+pub struct Vulkan {
+    // same device as context.device
     device: ash::Device,
     physical_device: vk::PhysicalDevice,
-    physical_device_memory_properties: vk::PhysicalDeviceMemoryProperties,
 
-    command_pool: vk::CommandPool,
+    command_pool: resource::CommandPool,
     command_buffers: Vec<vk::CommandBuffer>,
-    transient_command_pool: vk::CommandPool,
+    transient_command_pool: resource::CommandPool,
 
     graphics_queue: vk::Queue,
-    present_queue: vk::Queue,
-
     graphics_family_index: u32,
-    present_family_index: u32,
 
-    swapchain: Swapchain,
-    pub swapchain_extent: vk::Extent2D,
-    swapchain_khr: vk::SwapchainKHR,
-    swapchain_present_mode: vk::PresentModeKHR,
-    swapchain_images: Vec<vk::Image>,
-    swapchain_image_views: Vec<vk::ImageView>,
-    swapchain_framebuffers: Vec<vk::Framebuffer>,
+    // same as the graphics queue and pool when there is no dedicated transfer family
+    transfer_queue: vk::Queue,
+    transfer_family_index: u32,
+    transfer_command_pool: resource::CommandPool,
 
-    render_pass: vk::RenderPass,
-    pipeline: vk::Pipeline,
-    pipeline_layout: vk::PipelineLayout,
+    target: RenderTarget,
+    pub extent: vk::Extent2D,
 
-    image_available_semaphores: [vk::Semaphore; FRAMES_IN_FLIGHT as usize],
-    render_finished_semaphores: [vk::Semaphore; FRAMES_IN_FLIGHT as usize],
-    in_flight_fences: [vk::Fence; FRAMES_IN_FLIGHT as usize],
+    render_pass: resource::RenderPass,
+    pipeline: resource::Pipeline,
+    pipeline_layout: resource::PipelineLayout,
+    // main.vert with INSTANCED, for draw_instanced
+    instanced_pipeline: resource::Pipeline,
+    instanced_pipeline_layout: resource::PipelineLayout,
+    // for the permutations and options that were not embedded at build time
+    shader_compiler: shaders::ShaderCompiler,
 
-    current_frame: usize,
+    // kept to rebuild the pipeline when its shaders change
+    #[cfg(feature = "hot-reload")]
+    hot_reload: HotReload,
 
-    vertex_buffer: vk::Buffer,
-    vertex_memory: vk::DeviceMemory,
+    // one per frame in flight
+    image_available_semaphores: Vec<resource::Semaphore>,
+    render_finished_semaphores: Vec<resource::Semaphore>,
+    in_flight_fences: Vec<resource::Fence>,
 
-    index_buffer: vk::Buffer,
-    index_memory: vk::DeviceMemory,
-    indices_len: u32,
+    current_frame: usize,
 
-    camera_buffer: vk::Buffer,
-    camera_memory: vk::DeviceMemory,
+    geometry: mesh::Geometry,
+    // indexed by TextureId
+    textures: Vec<texture::Texture>,
+    mip_generation: texture::MipGeneration,
+    pub scene: scene::Scene,
+    instanced_draws: instancing::InstancedDraws,
+    instance_buffer: buffer::PerFrameBuffer,
+    // false when drawing from the cpu, see Config::cpu_draws
+    indirect_draws: bool,
+    // 1 without the multiDrawIndirect feature
+    max_draw_indirect_count: u32,
+    // whether culling compacts the draws of each texture and the gpu provides their count
+    draw_indirect_count: bool,
+    // the cpu draws are culled against its frustum as well
+    culling: culling::Culling,
+    // culling runs on the graphics queue when None
+    async_compute: Option<async_compute::AsyncCompute>,
+
+    camera_buffer: resource::Buffer,
     camera_mapped_ptr: *mut c_void,
     camera_buffer_stride: vk::DeviceSize,
 
-    descriptor_set_layout: vk::DescriptorSetLayout,
-    descriptor_set: vk::DescriptorSet,
-    descriptor_pool: vk::DescriptorPool,
-
-    image: vk::Image,
-    image_memory: vk::DeviceMemory,
-    image_sampler: vk::Sampler,
-    image_view: vk::ImageView,
+    descriptor_set_layout: resource::DescriptorSetLayout,
+    descriptor_pool: resource::DescriptorPool,
+    image_sampler: resource::Sampler,
 
-    depth_image: vk::Image,
-    depth_image_view: vk::ImageView,
-    depth_image_memory: vk::DeviceMemory,
+    depth_image: resource::Image,
+    depth_image_view: resource::ImageView,
     depth_format: vk::Format,
+
+    screenshot_path: Option<PathBuf>,
+    pipeline_cache_path: Option<PathBuf>,
+
+    // fields are dropped in declaration order, so this goes last
+    context: Context,
 }
 
 impl Vulkan {
-    pub fn new(window: &winit::window::Window) -> Self {
+    pub fn new(window: &winit::window::Window, config: &Config) -> Result<Self, RendererError> {
         let entry = ash::Entry::linked();
-        let instance = init::create_instance(&entry); 
-    
-        let debug_utils = DebugUtils::new(&entry, &instance);
-        let debug_messenger = init::create_messenger(&debug_utils);
+        let instance = init::create_instance(
+            &entry,
+            Some(window.display_handle().map_err(|_| RendererError::UnsupportedWindow)?.as_raw()),
+        )?;
 
         let surface = Surface::new(&entry, &instance);
         let surface_khr = unsafe { init::create_surface(
             &entry,
             &instance,
             window,
-        )};
+        )}?;
+
+        let size = window.inner_size();
+        Self::with_surface(
+            entry,
+            instance,
+            Some((surface, surface_khr)),
+            Extent2D {
+                width: size.width,
+                height: size.height,
+            },
+            config,
+        )
+    }
 
-        // "fmi" means family index 
-        let (physical_device, queue_family_indices) = device::get_physical_device_and_queue_family_indices(&instance, &surface, surface_khr);
-        let [graphics_family_index, present_family_index] = queue_family_indices;
-        let (device, [graphics_queue, present_queue]) = device::create_logical_device_and_queues(&instance, physical_device, &queue_family_indices);
+    // renders into an offscreen color image instead of a swapchain,
+    // needs no window so it can run on software drivers such as lavapipe
+    pub fn new_headless(extent: vk::Extent2D, config: &Config) -> Result<Self, RendererError> {
+        let entry = ash::Entry::linked();
+        let instance = init::create_instance(&entry, None)?;
+
+        Self::with_surface(entry, instance, None, extent, config)
+    }
 
-        let command_pool = unsafe {    
+    fn with_surface(
+        entry: ash::Entry,
+        instance: ash::Instance,
+        surface: Option<(Surface, SurfaceKHR)>,
+        mut extent: vk::Extent2D,
+        config: &Config,
+    ) -> Result<Self, RendererError> {
+        let debug_utils = DebugUtils::new(&entry, &instance);
+        let debug_messenger = init::create_messenger(&debug_utils)?;
+
+        let (physical_device, queue_family_indices) = device::get_physical_device_and_queue_family_indices(
+            &instance,
+            surface.as_ref().map(|(surface, surface_khr)| (surface, *surface_khr)),
+            config.device.as_ref(),
+        )?;
+        let graphics_family_index = queue_family_indices.graphics;
+        let transfer_family_index = queue_family_indices.transfer.unwrap_or(graphics_family_index);
+        let (device, queues, features) = device::create_logical_device_and_queues(&instance, physical_device, &queue_family_indices)?;
+        let graphics_queue = queues.graphics;
+        let transfer_queue = queues.transfer.unwrap_or(graphics_queue);
+
+        let physical_device_memory_properties = unsafe{instance.get_physical_device_memory_properties(physical_device)};
+        let physical_device_properties = unsafe{instance.get_physical_device_properties(physical_device)};
+
+        // scene objects are drawn as instances, so each draw needs its own first instance
+        let indirect_draws = !config.cpu_draws && features.core.draw_indirect_first_instance == vk::TRUE;
+        if !config.cpu_draws && !indirect_draws {
+            log::info!("Drawing from the cpu as drawIndirectFirstInstance is not supported");
+        }
+        let max_draw_indirect_count = match features.core.multi_draw_indirect {
+            vk::TRUE => physical_device_properties.limits.max_draw_indirect_count,
+            _ => 1,
+        };
+        let draw_indirect_count = features.draw_indirect_count && max_draw_indirect_count > 1;
+
+        // blits need linear filtering to downsample, which is optional for most formats
+        let texture_format_features = unsafe {
+            instance.get_physical_device_format_properties(physical_device, texture::FORMAT)
+        }.optimal_tiling_features;
+        let mip_generation = if texture_format_features.contains(
+            vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR | vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST,
+        ) {
+            texture::MipGeneration::Blit
+        } else {
+            log::info!("Generating mipmaps on the cpu as {:?} can not be blitted with linear filtering", texture::FORMAT);
+            texture::MipGeneration::Cpu
+        };
+        let pipeline_cache = pipeline_cache::load_pipeline_cache(
+            &device,
+            &physical_device_properties,
+            config.pipeline_cache_path.as_deref(),
+        )?;
+
+        // declared before any resource so that on an early return
+        // the resources are dropped first and the context destroys them
+        let mut context = Context {
+            allocator: allocator::Allocator::new(physical_device_memory_properties, &physical_device_properties.limits),
+            deletion_queue: resource::DeletionQueue::default(),
+            device: device.clone(),
+            instance,
+            debug_utils,
+            debug_messenger,
+            pipeline_cache,
+        };
+        let allocator = &mut context.allocator;
+        let deletion_queue = &context.deletion_queue;
+
+        let command_pool = resource::CommandPool::new(unsafe {
             device.create_command_pool(
                 &vk::CommandPoolCreateInfo::builder()
                     .queue_family_index(graphics_family_index)
                     .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER),
                 None
-            ).unwrap()
-        };
-        let transient_command_pool = unsafe {    
+            )?
+        }, deletion_queue);
+        let transient_command_pool = resource::CommandPool::new(unsafe {
             device.create_command_pool(
                 &vk::CommandPoolCreateInfo::builder()
                     .queue_family_index(graphics_family_index)
                     .flags(vk::CommandPoolCreateFlags::TRANSIENT),
                 None
-            ).unwrap()
-        };
+            )?
+        }, deletion_queue);
+        
+        let transfer_command_pool = resource::CommandPool::new(unsafe {
+            device.create_command_pool(
+                &vk::CommandPoolCreateInfo::builder()
+                    .queue_family_index(transfer_family_index)
+                    .flags(vk::CommandPoolCreateFlags::TRANSIENT),
+                None
+            )?
+        }, deletion_queue);
         
         let command_buffers = unsafe {    
             device.allocate_command_buffers(&
                 vk::CommandBufferAllocateInfo::builder()
-                .command_pool(command_pool)
+                .command_pool(command_pool.handle())
                 .level(vk::CommandBufferLevel::PRIMARY)
                 .command_buffer_count(FRAMES_IN_FLIGHT as u32)
-            ).unwrap()
+            )?
         };
 
-        let swapchain = Swapchain::new(&instance, &device);
-        
-        let surface_format = swapchain::choose_swapchain_format(unsafe{&surface
-            .get_physical_device_surface_formats(physical_device, surface_khr)
-            .unwrap()
-        });
-
-        let size = window.inner_size();
-        let mut swapchain_extent = Extent2D {
-            width: size.width,
-            height: size.height,
+        let async_compute = match (queue_family_indices.compute, queues.compute) {
+            (Some(family_index), Some(queue)) if config.async_compute && indirect_draws => {
+                Some(async_compute::AsyncCompute::new(&device, deletion_queue, queue, family_index)?)
+            }
+            _ => {
+                if config.async_compute && indirect_draws {
+                    log::info!("Culling on the graphics queue as there is no compute only queue family");
+                }
+                None
+            }
         };
 
-        let swapchain_present_mode = swapchain::choose_swapchain_present_mode(&unsafe { surface.get_physical_device_surface_present_modes(physical_device, surface_khr).unwrap() });
-
-        let (
-            swapchain_khr, 
-            swapchain_images, 
-            swapchain_image_views, 
-        ) = swapchain::create_swapchain_khr(
-            &mut swapchain_extent,
-            &surface,
-            surface_khr,
-            surface_format,
-            swapchain_present_mode,
-            &device,
-            &swapchain,
-            physical_device,
-            graphics_family_index,
-            present_family_index,
-        );
-
-        use pipeline::{Vertex, Index};
-        let vertices = &[
-            Vertex {
-                position: [-0.5, 1.0, 0.0],
-                color: [0.8, 1.0, 1.0],
-                tex_coord: [0.0, 1.0],
-            },
-            Vertex {
-                position: [1.0, 1.0, -1.0],
-                color: [0.0, 1.0, 0.0],
-                tex_coord: [1.0, 1.0],
-            },
-            Vertex {
-                position: [0.0, -1.0, 0.0],
-                color: [0.0, 0.0, 1.0],
-                tex_coord: [1.0, 0.0],
-            },
-            Vertex {
-                position: [1.0, 1.0, 1.0],
-                color: [1.0, 1.0, 1.0],
-                tex_coord: [0.0, 0.0],
-            },
-        ];
-        let indices: &[Index] = &[
-            0, 1, 2,
-            2, 3, 0,
-            1, 3, 2,
-        ];
+        // the swapchain is created before the depth image as it may clamp the extent,
+        // its framebuffers are filled in once the render pass exists
+        let mut swapchain_target = surface.map(|(surface, surface_khr)| -> Result<_, RendererError> {
+            let swapchain = Swapchain::new(&context.instance, &device);
 
-        let physical_device_memory_properties = unsafe{instance.get_physical_device_memory_properties(physical_device)};
+            let surface_format = swapchain::choose_swapchain_format(unsafe{&surface
+                .get_physical_device_surface_formats(physical_device, surface_khr)?
+            });
 
-        let vertex_buffer_size = (vertices.len() * size_of::<Vertex>()) as vk::DeviceSize;
-        let index_buffer_size = (indices.len() * size_of::<Index>()) as vk::DeviceSize;
-        let (vertex_buffer, vertex_memory) = buffer::create_buffer(
-            &device, 
-            &physical_device_memory_properties, 
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-            vertex_buffer_size,
-        );
+            let present_mode = swapchain::choose_swapchain_present_mode(&unsafe { surface.get_physical_device_surface_present_modes(physical_device, surface_khr)? });
+            let present_family_index = queue_family_indices.present.unwrap();
 
-        let (index_buffer, index_memory) = buffer::create_buffer(
-            &device, 
-            &physical_device_memory_properties, 
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-            index_buffer_size,
-        );
-
-        let physical_device_limits = unsafe{instance.get_physical_device_properties(physical_device)}.limits;
-        let camera_buffer_stride = physical_device_limits.min_uniform_buffer_offset_alignment.max(size_of::<camera::CameraRender>() as vk::DeviceSize);
+            let (
+                swapchain_khr, 
+                images, 
+                image_views, 
+            ) = swapchain::create_swapchain_khr(
+                &mut extent,
+                &surface,
+                surface_khr,
+                surface_format,
+                present_mode,
+                &device,
+                deletion_queue,
+                &swapchain,
+                physical_device,
+                graphics_family_index,
+                present_family_index,
+                vk::SwapchainKHR::null(),
+            )?;
+
+            Ok(swapchain::SwapchainTarget {
+                surface,
+                surface_khr,
+                surface_format,
+                present_queue: queues.present.unwrap(),
+                present_family_index,
+                swapchain,
+                swapchain_khr,
+                present_mode,
+                images,
+                image_views,
+                framebuffers: Vec::new(),
+            })
+        }).transpose()?;
+
+        let camera_buffer_stride = physical_device_properties.limits.min_uniform_buffer_offset_alignment.max(size_of::<camera::CameraRender>() as vk::DeviceSize);
         let camera_buffer_size = camera_buffer_stride * FRAMES_IN_FLIGHT as vk::DeviceSize;
-        let (camera_buffer, camera_memory) = buffer::create_buffer(
+        let camera_buffer = buffer::create_buffer(
             &device, 
-            &physical_device_memory_properties, 
+            allocator, 
+            deletion_queue,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
             vk::BufferUsageFlags::UNIFORM_BUFFER,
             camera_buffer_size,
-        );
-        let camera_mapped_ptr = unsafe{device.map_memory(camera_memory, 0, camera_buffer_size, vk::MemoryMapFlags::empty()).unwrap() as *mut c_void};
+        )?;
+        let camera_mapped_ptr = camera_buffer.allocation().mapped_ptr().unwrap() as *mut c_void;
 
-        // are we allocating memory for these bytes and then writing them to mapped_ptr,
-        // why not directly load these bytes into staging_mapped_ptr?
-        let image_buffer = image::open("C:/users/snick/dev/ash_learn/Apple.png").unwrap().to_rgba8();
-        let image_bytes = image_buffer.as_bytes();
-        let image_size = image_bytes.len() as vk::DeviceSize;
-
-        let (image, image_memory) = img::create_image(
-            &device, 
-            &physical_device_memory_properties, 
-            image_buffer.width(), 
-            image_buffer.height(), 
-            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED, 
-            vk::Format::R8G8B8A8_SRGB,
-            vk::ImageTiling::OPTIMAL, 
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        );
-
-        let image_view = img::create_image_view(
-            &device, 
-            image, 
-            vk::Format::R8G8B8A8_SRGB, 
-            vk::ImageAspectFlags::COLOR,
-        );
+        let assets = Assets::new(config.asset_root.as_deref())?;
 
-        let image_sampler = unsafe{device.create_sampler(
+        let image_sampler = resource::Sampler::new(unsafe{device.create_sampler(
             &vk::SamplerCreateInfo::builder()
                 .mag_filter(vk::Filter::LINEAR)
                 .min_filter(vk::Filter::LINEAR)
+                .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+                // every level, texture views cover their whole mip chain
                 .min_lod(0.0)
-                .max_lod(0.0)
+                .max_lod(vk::LOD_CLAMP_NONE)
                 .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
                 .anisotropy_enable(true)
-                .max_anisotropy(physical_device_limits.max_sampler_anisotropy)
+                .max_anisotropy(physical_device_properties.limits.max_sampler_anisotropy)
                 .unnormalized_coordinates(false)
                 .address_mode_u(vk::SamplerAddressMode::REPEAT)
                 .address_mode_v(vk::SamplerAddressMode::REPEAT)
                 .address_mode_w(vk::SamplerAddressMode::REPEAT)
                 .build(),
             None,    
-        )}.unwrap();
+        )}?, deletion_queue);
 
-        unsafe {
-            let (staging_buffer, staging_memory) = buffer::create_buffer(
-                &device, 
-                &physical_device_memory_properties, 
-                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-                vk::BufferUsageFlags::TRANSFER_SRC,
-                vertex_buffer_size + index_buffer_size + image_size,
-            );
+        let mut shader_compiler = shaders::ShaderCompiler::new();
+        let pipeline_builder = pipeline::GraphicsPipelineBuilder::new(shaders::MAIN_VERT, shaders::MAIN_FRAG)
+            .vertex_layout(vertex::VertexLayout::default().with::<vertex::Vertex>());
 
-            {
-                let ptr = device.map_memory(staging_memory, 0, vertex_buffer_size, vk::MemoryMapFlags::empty()).unwrap();
-                (ptr as *mut Vertex).copy_from(vertices.as_ptr(), vertices.len());
-                (ptr.add(vertex_buffer_size as usize) as *mut Index).copy_from(indices.as_ptr(), indices.len());
-                (ptr.add((vertex_buffer_size + index_buffer_size) as usize) as *mut u8).copy_from(image_bytes.as_ptr(), image_bytes.len());
-                device.unmap_memory(staging_memory);
+        let mut descriptor_bindings = pipeline_builder.reflect(&mut shader_compiler)?.sets.into_iter().next().unwrap_or_default();
+        for binding in &mut descriptor_bindings {
+            // the camera buffer holds one view per frame in flight, selected with a dynamic offset
+            if binding.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER {
+                binding.descriptor_type = vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC;
             }
-
-            let command_buffer = command_buffers[0];
-
-            device.begin_command_buffer(command_buffer, &vk::CommandBufferBeginInfo::default()).unwrap();
-
-            device.cmd_copy_buffer(
-                command_buffer, 
-                staging_buffer, 
-                vertex_buffer, 
-                &[vk::BufferCopy{
-                    src_offset: 0,
-                    dst_offset: 0,
-                    size: vertex_buffer_size,
-                }]
-            );
-
-            device.cmd_copy_buffer(
-                command_buffer, 
-                staging_buffer, 
-                index_buffer, 
-                &[vk::BufferCopy{
-                    src_offset: vertex_buffer_size,
-                    dst_offset: 0,
-                    size: index_buffer_size,
-                }]
-            );
-
-            let stage_0 = vk::PipelineStageFlags::TOP_OF_PIPE;
-            let stage_1 = vk::PipelineStageFlags::TRANSFER;
-            let stage_2 = vk::PipelineStageFlags::FRAGMENT_SHADER;
-
-            let access_0 = vk::AccessFlags::empty();
-            let access_1 = vk::AccessFlags::TRANSFER_WRITE;
-            let access_2 = vk::AccessFlags::SHADER_READ;
-
-            let layout_0 = vk::ImageLayout::UNDEFINED;
-            let layout_1 = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
-            let layout_2 = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
-
-            device.cmd_pipeline_barrier(
-                command_buffer, 
-                stage_0, 
-                stage_1,
-                vk::DependencyFlags::empty(), 
-                &[], 
-                &[], 
-                &[
-                    vk::ImageMemoryBarrier::builder()
-                        .image(image)
-                        .subresource_range(vk::ImageSubresourceRange {
-                            aspect_mask: vk::ImageAspectFlags::COLOR,
-                            base_mip_level: 0,
-                            level_count: 1,
-                            base_array_layer: 0,
-                            layer_count: 1,
-                        })
-                        .old_layout(layout_0)
-                        .new_layout(layout_1)
-                        .src_access_mask(access_0)
-                        .dst_access_mask(access_1)
-                        .build()
-                ]
-            );
-
-            device.cmd_copy_buffer_to_image(
-                command_buffer, 
-                staging_buffer, 
-                image, 
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL, 
-                &[
-                    vk::BufferImageCopy::builder()
-                        .buffer_offset(vertex_buffer_size + index_buffer_size)
-                        .image_subresource(vk::ImageSubresourceLayers {
-                            aspect_mask: vk::ImageAspectFlags::COLOR,
-                            mip_level: 0,
-                            base_array_layer: 0,
-                            layer_count: 1,
-                        })
-                        .image_extent(vk::Extent3D {
-                            width: image_buffer.width(),
-                            height: image_buffer.height(),
-                            depth: 1,
-                        })
-                        .build()
-                ]
-            );
-
-            device.cmd_pipeline_barrier(
-                command_buffer, 
-                stage_1,
-                stage_2,
-                vk::DependencyFlags::empty(), 
-                &[], 
-                &[], 
-                &[
-                    vk::ImageMemoryBarrier::builder()
-                        .image(image)
-                        .subresource_range(vk::ImageSubresourceRange {
-                            aspect_mask: vk::ImageAspectFlags::COLOR,
-                            base_mip_level: 0,
-                            level_count: 1,
-                            base_array_layer: 0,
-                            layer_count: 1,
-                        })
-                        .old_layout(layout_1)
-                        .new_layout(layout_2)
-                        .src_access_mask(access_1)
-                        .dst_access_mask(access_2)
-                        .build()
-                ]
-            );
-
-            device.end_command_buffer(command_buffer).unwrap();
-
-            device.queue_submit(
-                graphics_queue, 
-                &[vk::SubmitInfo::builder()
-                    .command_buffers(&[command_buffer])
-                    .build()], 
-                vk::Fence::null()
-            ).unwrap();
-
-            device.queue_wait_idle(graphics_queue).unwrap();
-
-            device.free_memory(staging_memory, None);
-            device.destroy_buffer(staging_buffer, None);    
         }
+        let descriptor_set_layout = resource::DescriptorSetLayout::new(
+            unsafe {
+                device.create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_bindings),
+                    None,
+                )?
+            },
+            &descriptor_bindings,
+            deletion_queue,
+        );
 
-        let descriptor_set_layout = unsafe {device.create_descriptor_set_layout(
-            &vk::DescriptorSetLayoutCreateInfo::builder()
-            .bindings(&[
-                vk::DescriptorSetLayoutBinding::builder()
-                    .binding(0)
-                    .descriptor_count(1)
-                    .stage_flags(vk::ShaderStageFlags::VERTEX)
-                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
-                    .build(),
-                vk::DescriptorSetLayoutBinding::builder()
-                    .binding(1)
-                    .descriptor_count(1)
-                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
-                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                    .build()
-            ]), 
-            None,
-        ).unwrap()};
-
-        let descriptor_pool = unsafe{device.create_descriptor_pool(
+        let descriptor_pool = resource::DescriptorPool::new(unsafe{device.create_descriptor_pool(
             &vk::DescriptorPoolCreateInfo::builder()
-                .max_sets(1)
-                .pool_sizes(&[
-                    vk::DescriptorPoolSize {
-                        ty: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
-                        descriptor_count: 1,
-                    },
-                    vk::DescriptorPoolSize {
-                        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                        descriptor_count: 1,
-                    },
-                ])
+                .max_sets(MAX_TEXTURES)
+                .pool_sizes(&descriptor_bindings
+                    .iter()
+                    .map(|binding| vk::DescriptorPoolSize {
+                        ty: binding.descriptor_type,
+                        descriptor_count: binding.descriptor_count * MAX_TEXTURES,
+                    })
+                    .collect::<Vec<_>>())
             , None
-        ).unwrap()};
-
-        let descriptor_set = unsafe{device.allocate_descriptor_sets(
-            &vk::DescriptorSetAllocateInfo::builder()
-                .descriptor_pool(descriptor_pool)
-                .set_layouts(&[descriptor_set_layout])
-                .build(),
-        ).unwrap()[0]};
-
-        unsafe {
-            device.update_descriptor_sets(
-                &[
-                    vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
-                        .dst_binding(0)
-                        .dst_array_element(0)
-                        .buffer_info(&[
-                            vk::DescriptorBufferInfo {
-                                buffer: camera_buffer,
-                                offset: 0,
-                                // do not pass size of whole camera buffer 
-                                range: camera_buffer_stride,
-                            },
-                        ])
-                        .build(),
-                    vk::WriteDescriptorSet::builder()
-                        .dst_set(descriptor_set)
-                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .dst_binding(1)
-                        .dst_array_element(0)
-                        .image_info(&[
-                            vk::DescriptorImageInfo {
-                                sampler: image_sampler,
-                                image_view,
-                                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                            }
-                        ])
-                        .build(),
-                ], 
-                &[],
-            );
-        }
+        )?}, deletion_queue);
 
         let depth_format = device::find_depth_format(
-            &instance, 
+            &context.instance, 
             physical_device, 
             &[vk::Format::D32_SFLOAT, vk::Format::D32_SFLOAT_S8_UINT, vk::Format::D24_UNORM_S8_UINT], 
             vk::ImageTiling::OPTIMAL,
             vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,    
-        );
+        )?;
 
-        let (depth_image, depth_image_memory) = img::create_image(
+        let depth_image = img::create_image(
             &device, 
-            &physical_device_memory_properties, 
-            swapchain_extent.width, 
-            swapchain_extent.height, 
+            allocator, 
+            deletion_queue,
+            extent.width, 
+            extent.height, 
+            1,
+            1,
             vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, 
             depth_format, 
             vk::ImageTiling::OPTIMAL, 
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        );
+        )?;
         let depth_image_view = img::create_image_view(
             &device, 
-            depth_image, 
+            deletion_queue,
+            depth_image.handle(), 
             depth_format,
             vk::ImageAspectFlags::DEPTH, 
-        );
-        
-        let render_pass = render_pass::create_render_pass(
-            &device, 
-            surface_format.format, 
-            depth_format
-        );
+        )?;
         
-        let swapchain_framebuffers = swapchain::create_swapchain_framebuffers(
-            &device, 
-            &swapchain_image_views, 
-            depth_image_view,
-            render_pass,
-            swapchain_extent,
-        );
+        let render_pass = match &swapchain_target {
+            Some(target) => render_pass::create_render_pass(
+                &device, 
+                deletion_queue,
+                target.surface_format.format, 
+                depth_format,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            )?,
+            None => render_pass::create_render_pass(
+                &device, 
+                deletion_queue,
+                offscreen::OFFSCREEN_FORMAT, 
+                depth_format,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            )?,
+        };
 
-        let shader_compiler = shaderc::Compiler::new().unwrap();
+        let target = match swapchain_target.take() {
+            Some(mut target) => {
+                target.framebuffers = swapchain::create_swapchain_framebuffers(
+                    &device, 
+                    deletion_queue,
+                    &target.image_views, 
+                    depth_image_view.handle(),
+                    render_pass.handle(),
+                    extent,
+                )?;
+                RenderTarget::Swapchain(target)
+            }
+            None => RenderTarget::Offscreen(offscreen::create_offscreen_target(
+                &device,
+                allocator,
+                deletion_queue,
+                extent,
+                depth_image_view.handle(),
+                render_pass.handle(),
+            )?),
+        };
 
-        let (pipeline, pipeline_layout) = pipeline::new_pipeline_and_layout(
-            &device, 
-            descriptor_set_layout,
-            &shader_compiler, 
-            render_pass, "C:/users/snick/dev/ash_learn/src/shaders/main.vert", 
-            "C:/users/snick/dev/ash_learn/src/shaders/main.frag"
-        );
+        for (heap_index, heap) in allocator.stats().iter().enumerate() {
+            log::debug!(
+                "Heap {}: {} allocations using {} of {} bytes in {} blocks",
+                heap_index, heap.allocation_count, heap.used_bytes, heap.block_bytes, heap.block_count,
+            );
+        }
+
+        let pipeline_builder = pipeline_builder.set_layouts(&[&descriptor_set_layout]);
+        let (pipeline, pipeline_layout) = pipeline_builder.build(
+            &device,
+            deletion_queue,
+            &mut shader_compiler,
+            pipeline_cache,
+            render_pass.handle(),
+        )?;
+
+        let instanced_pipeline_builder = pipeline::GraphicsPipelineBuilder::new(shaders::MAIN_VERT, shaders::MAIN_FRAG)
+            .define("INSTANCED", None)
+            .vertex_layout(vertex::VertexLayout::default().with::<vertex::Vertex>().with::<Instance>())
+            .set_layouts(&[&descriptor_set_layout]);
+        let (instanced_pipeline, instanced_pipeline_layout) = instanced_pipeline_builder.build(
+            &device,
+            deletion_queue,
+            &mut shader_compiler,
+            pipeline_cache,
+            render_pass.handle(),
+        )?;
+
+        // the draws are read by the graphics queue after being written by the compute queue
+        let culling_family_indices = match &async_compute {
+            Some(async_compute) => vec![graphics_family_index, async_compute.family_index],
+            None => Vec::new(),
+        };
+        let culling = culling::Culling::new(
+            &device,
+            deletion_queue,
+            &mut shader_compiler,
+            pipeline_cache,
+            &culling_family_indices,
+        )?;
+
+        #[cfg(feature = "hot-reload")]
+        let hot_reload = HotReload {
+            // every file, as any of them may be included
+            shader_watcher: shader_watcher::ShaderWatcher::new(
+                std::fs::read_dir(shader_compile::shaders_dir())
+                    .map_err(|source| RendererError::Io {
+                        path: shader_compile::shaders_dir().to_path_buf(),
+                        source,
+                    })?
+                    .filter_map(|entry| Some(entry.ok()?.path())),
+            ),
+            pipeline_builder,
+            instanced_pipeline_builder,
+            cull_pipeline_builder: culling.pipeline_builder(),
+        };
 
-        let mut image_available_semaphores = [Default::default(); FRAMES_IN_FLIGHT as usize];
-        let mut render_finished_semaphores = [Default::default(); FRAMES_IN_FLIGHT as usize];
-        let mut in_flight_fences = [Default::default(); FRAMES_IN_FLIGHT as usize];
+        let mut image_available_semaphores = Vec::with_capacity(FRAMES_IN_FLIGHT as usize);
+        let mut render_finished_semaphores = Vec::with_capacity(FRAMES_IN_FLIGHT as usize);
+        let mut in_flight_fences = Vec::with_capacity(FRAMES_IN_FLIGHT as usize);
 
         let semaphore_info = &vk::SemaphoreCreateInfo::builder();
         let fence_info = &vk::FenceCreateInfo::builder()
             .flags(vk::FenceCreateFlags::SIGNALED);
 
-        for frame in 0..FRAMES_IN_FLIGHT as usize {
+        for _ in 0..FRAMES_IN_FLIGHT {
             unsafe {
-                image_available_semaphores[frame] = device.create_semaphore(&semaphore_info, None).unwrap();
-                render_finished_semaphores[frame] = device.create_semaphore(&semaphore_info, None).unwrap();
-                in_flight_fences[frame] = device.create_fence(&fence_info, None).unwrap();
+                image_available_semaphores.push(resource::Semaphore::new(device.create_semaphore(semaphore_info, None)?, deletion_queue));
+                render_finished_semaphores.push(resource::Semaphore::new(device.create_semaphore(semaphore_info, None)?, deletion_queue));
+                in_flight_fences.push(resource::Fence::new(device.create_fence(fence_info, None)?, deletion_queue));
             }
         }
 
-        Self {
-            instance,
-
-            debug_utils,
-            debug_messenger,
-
-            surface,
-            surface_khr,
-            surface_format,
-
-            physical_device,
-            physical_device_memory_properties,
-
+        let mut vulkan = Self {
             device,
-
+            physical_device,
 
             command_pool,
             command_buffers,
             transient_command_pool,
 
             graphics_queue,
-            present_queue,
-
             graphics_family_index,
-            present_family_index,
 
+            transfer_queue,
+            transfer_family_index,
+            transfer_command_pool,
 
-            swapchain,
-            swapchain_khr,
-            swapchain_extent,
-            swapchain_present_mode,
-            swapchain_images,
-            swapchain_image_views,
-            swapchain_framebuffers,
+            target,
+            extent,
 
             render_pass,
             pipeline,
             pipeline_layout,
+            instanced_pipeline,
+            instanced_pipeline_layout,
+            shader_compiler,
+
+            #[cfg(feature = "hot-reload")]
+            hot_reload,
+
             current_frame: 0,
             image_available_semaphores,
             render_finished_semaphores,
             in_flight_fences,
 
-            vertex_buffer,
-            vertex_memory,
+            geometry: mesh::Geometry::default(),
+            textures: Vec::new(),
+            mip_generation,
+            scene: scene::Scene::default(),
+            instanced_draws: instancing::InstancedDraws::default(),
+            instance_buffer: buffer::PerFrameBuffer::new(vk::BufferUsageFlags::VERTEX_BUFFER),
+            indirect_draws,
+            max_draw_indirect_count,
+            draw_indirect_count,
+            culling,
+            async_compute,
 
-            index_buffer,
-            index_memory,
-            indices_len: indices.len() as u32,
-            
             camera_buffer,
-            camera_memory,
             camera_mapped_ptr,
             camera_buffer_stride,
 
             descriptor_set_layout,
             descriptor_pool,
-            descriptor_set,
-            
-            image,
-            image_memory,
             image_sampler,
-            image_view,
 
             depth_image,
             depth_image_view,
-            depth_image_memory,
             depth_format,
+
+            screenshot_path: None,
+            pipeline_cache_path: config.pipeline_cache_path.clone(),
+
+            context,
+        };
+
+        let default_texture = image::load_from_memory(&assets.read("apple.png")?)?.to_rgba8();
+        vulkan.upload_texture(&default_texture)?;
+
+        Ok(vulkan)
+    }
+
+    // drawn by adding objects using it to the scene,
+    // the shared mesh buffers are rebuilt with it by the next draw_frame
+    pub fn upload_mesh(&mut self, mesh: &MeshData) -> mesh::MeshId {
+        self.geometry.add(mesh)
+    }
+
+    // queues `instances` of `mesh` for the next draw_frame only, drawn with a single draw call,
+    // the texture layer of each instance indexes into `texture`
+    pub fn draw_instanced(&mut self, mesh: mesh::MeshId, texture: texture::TextureId, instances: &[Instance]) {
+        self.instanced_draws.push(mesh, texture, instances);
+    }
+
+    pub fn upload_texture(&mut self, pixels: &image::RgbaImage) -> Result<texture::TextureId, RendererError> {
+        self.upload_texture_layers(std::slice::from_ref(pixels))
+    }
+
+    // layers of the same size, picked per instance by Instance::texture_layer
+    pub fn upload_texture_layers(&mut self, layers: &[image::RgbaImage]) -> Result<texture::TextureId, RendererError> {
+        let upload_queues = self.upload_queues();
+        let (image, image_view) = texture::create_texture_image(
+            &self.device,
+            &mut self.context.allocator,
+            &self.context.deletion_queue,
+            &upload_queues,
+            layers,
+            self.mip_generation,
+        )?;
+
+        let descriptor_set = unsafe{self.device.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(self.descriptor_pool.handle())
+                .set_layouts(&[self.descriptor_set_layout.handle()])
+                .build(),
+        )?[0]};
+
+        unsafe {
+            self.device.update_descriptor_sets(
+                &[
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_set)
+                        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+                        .dst_binding(0)
+                        .dst_array_element(0)
+                        .buffer_info(&[
+                            vk::DescriptorBufferInfo {
+                                buffer: self.camera_buffer.handle(),
+                                offset: 0,
+                                // do not pass size of whole camera buffer 
+                                range: self.camera_buffer_stride,
+                            },
+                        ])
+                        .build(),
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_set)
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .dst_binding(1)
+                        .dst_array_element(0)
+                        .image_info(&[
+                            vk::DescriptorImageInfo {
+                                sampler: self.image_sampler.handle(),
+                                image_view: image_view.handle(),
+                                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                            }
+                        ])
+                        .build(),
+                ], 
+                &[],
+            );
+        }
+
+        self.textures.push(texture::Texture {
+            image,
+            image_view,
+            descriptor_set,
+        });
+        Ok(texture::TextureId(self.textures.len() - 1))
+    }
+
+    fn upload_queues(&self) -> upload::UploadQueues {
+        upload::UploadQueues {
+            transfer_queue: self.transfer_queue,
+            transfer_family_index: self.transfer_family_index,
+            transfer_command_pool: self.transfer_command_pool.handle(),
+            graphics_queue: self.graphics_queue,
+            graphics_family_index: self.graphics_family_index,
+            graphics_command_pool: self.transient_command_pool.handle(),
         }
     }
 
     pub fn update_camera(&mut self, camera: &camera::Camera) {
+        let render = camera.to_render();
+        if !self.culling.frozen {
+            self.culling.frustum_planes = render.frustum_planes();
+        }
         unsafe {
             let offset = self.current_frame * self.camera_buffer_stride as usize;
-            (self.camera_mapped_ptr.add(offset) as *mut camera::CameraRender).write(camera.to_render());
+            (self.camera_mapped_ptr.add(offset) as *mut camera::CameraRender).write(render);
         }
     }
 
-    pub fn renew_swapchain(&mut self) {
-        unsafe {
-            self.device.device_wait_idle().unwrap();
+    // while frozen, objects are culled against the frustum of the last update_camera before freezing
+    pub fn freeze_culling(&mut self, frozen: bool) {
+        self.culling.frozen = frozen;
+    }
 
-            self.device.destroy_image_view(self.depth_image_view, None);
-            self.device.free_memory(self.depth_image_memory, None);
-            self.device.destroy_image(self.depth_image, None);
+    pub fn culling_frozen(&self) -> bool {
+        self.culling.frozen
+    }
 
-            for i in 0..self.swapchain_image_views.len() {
-                self.device.destroy_framebuffer(self.swapchain_framebuffers[i], None);
-                self.device.destroy_image_view(self.swapchain_image_views[i], None);   
+    pub fn renew_swapchain(&mut self) -> Result<(), RendererError> {
+        unsafe {
+            self.device.device_wait_idle()?;
+
+            // the old framebuffers, views and images go to the deletion queue once replaced
+            if let RenderTarget::Swapchain(target) = &mut self.target {
+                target.framebuffers.clear();
+
+                // handing over the old swapchain lets the presentation engine
+                // keep showing its images until the new ones are presented
+                let old_swapchain_khr = target.swapchain_khr;
+                (
+                    target.swapchain_khr,
+                    target.images,
+                    target.image_views,
+                ) = swapchain::create_swapchain_khr(
+                    &mut self.extent,
+                    &target.surface,
+                    target.surface_khr,
+                    target.surface_format,
+                    target.present_mode,
+                    &self.device,
+                    &self.context.deletion_queue,
+                    &target.swapchain,
+                    self.physical_device,
+                    self.graphics_family_index,
+                    target.present_family_index,
+                    old_swapchain_khr,
+                )?;
+                target.swapchain.destroy_swapchain(old_swapchain_khr, None);
             }
-            self.swapchain.destroy_swapchain(self.swapchain_khr, None);
 
-            (
-                self.swapchain_khr,
-                self.swapchain_images,
-                self.swapchain_image_views,
-            ) = swapchain::create_swapchain_khr(
-                &mut self.swapchain_extent,
-                &self.surface,
-                self.surface_khr,
-                self.surface_format,
-                self.swapchain_present_mode,
-                &self.device,
-                &self.swapchain,
-                self.physical_device,
-                self.graphics_family_index,
-                self.present_family_index,
-            );
-
-            (self.depth_image, self.depth_image_memory) = img::create_image(
+            self.depth_image = img::create_image(
                 &self.device,
-                &self.physical_device_memory_properties,
-                self.swapchain_extent.width,
-                self.swapchain_extent.height,
+                &mut self.context.allocator,
+                &self.context.deletion_queue,
+                self.extent.width,
+                self.extent.height,
+                1,
+                1,
                 vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
                 self.depth_format,
                 vk::ImageTiling::OPTIMAL,
                 vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            );
+            )?;
 
             self.depth_image_view = img::create_image_view(
                 &self.device, 
-                self.depth_image, 
+                &self.context.deletion_queue,
+                self.depth_image.handle(), 
                 self.depth_format, 
                 vk::ImageAspectFlags::DEPTH
-            );
+            )?;
     
-            self.swapchain_framebuffers = swapchain::create_swapchain_framebuffers(
-                &self.device, 
-                &self.swapchain_image_views, 
-                self.depth_image_view,
-                self.render_pass,
-                self.swapchain_extent,
-            );
+            match &mut self.target {
+                RenderTarget::Swapchain(target) => {
+                    target.framebuffers = swapchain::create_swapchain_framebuffers(
+                        &self.device, 
+                        &self.context.deletion_queue,
+                        &target.image_views, 
+                        self.depth_image_view.handle(),
+                        self.render_pass.handle(),
+                        self.extent,
+                    )?;
+                }
+                RenderTarget::Offscreen(target) => {
+                    *target = offscreen::create_offscreen_target(
+                        &self.device,
+                        &mut self.context.allocator,
+                        &self.context.deletion_queue,
+                        self.extent,
+                        self.depth_image_view.handle(),
+                        self.render_pass.handle(),
+                    )?;
+                }
+            }
         }
+
+        Ok(())
     }
 
-    pub fn draw_frame(&mut self) {
+    // on a compile error the previous pipeline is kept and the diagnostics are logged,
+    // only errors from waiting on the device are returned
+    #[cfg(feature = "hot-reload")]
+    fn reload_changed_shaders(&mut self) -> Result<(), RendererError> {
+        if !self.hot_reload.shader_watcher.poll() {
+            return Ok(());
+        }
+
+        // the frames in flight may still be using the old pipeline
+        unsafe { self.device.device_wait_idle()? };
+        self.shader_compiler.clear_cache();
+
+        // both share main.vert, so either both are replaced or neither
+        let mut build = |builder: &pipeline::GraphicsPipelineBuilder| builder.build_from_source(
+            &self.device,
+            &self.context.deletion_queue,
+            &mut self.shader_compiler,
+            self.context.pipeline_cache,
+            self.render_pass.handle(),
+        );
+        match build(&self.hot_reload.pipeline_builder).and_then(|pipeline| {
+            Ok((pipeline, build(&self.hot_reload.instanced_pipeline_builder)?))
+        }) {
+            Ok(((pipeline, pipeline_layout), (instanced_pipeline, instanced_pipeline_layout))) => {
+                self.pipeline = pipeline;
+                self.pipeline_layout = pipeline_layout;
+                self.instanced_pipeline = instanced_pipeline;
+                self.instanced_pipeline_layout = instanced_pipeline_layout;
+                log::info!("Reloaded shaders");
+            }
+            Err(err) => log::error!("Keeping the previous pipelines, {}", err),
+        }
+
+        match self.hot_reload.cull_pipeline_builder.build_from_source(
+            &self.device,
+            &self.context.deletion_queue,
+            &mut self.shader_compiler,
+            self.context.pipeline_cache,
+        ) {
+            Ok(pipeline) => self.culling.pipeline = pipeline,
+            Err(err) => log::error!("Keeping the previous culling pipeline, {}", err),
+        }
+        Ok(())
+    }
+
+    // an out of date or suboptimal swapchain is renewed here,
+    // any other error from acquiring, submitting or presenting is returned
+    pub fn draw_frame(&mut self) -> Result<(), RendererError> {
+        #[cfg(feature = "hot-reload")]
+        self.reload_changed_shaders()?;
+
         unsafe {
-            let image_available_semaphore = self.image_available_semaphores[self.current_frame];
-            let render_finished_semaphore = self.render_finished_semaphores[self.current_frame];
-            let in_flight_fence = self.in_flight_fences[self.current_frame];
+            let image_available_semaphore = self.image_available_semaphores[self.current_frame].handle();
+            let render_finished_semaphore = self.render_finished_semaphores[self.current_frame].handle();
+            let in_flight_fence = self.in_flight_fences[self.current_frame].handle();
             let command_buffer = self.command_buffers[self.current_frame];
     
             let fences = &[in_flight_fence];
-            self.device.wait_for_fences(fences, true, u64::MAX).unwrap();
-            self.device.reset_fences(fences).unwrap();
+            self.device.wait_for_fences(fences, true, u64::MAX)?;
+            self.context.deletion_queue.begin_frame(&self.device, &mut self.context.allocator, self.current_frame);
+
+            let upload_queues = self.upload_queues();
+            self.geometry.flush(&self.device, &mut self.context.allocator, &self.context.deletion_queue, &upload_queues)?;
     
-            let image_index = match self.swapchain.acquire_next_image(
-                self.swapchain_khr, 
-                u64::MAX, 
-                image_available_semaphore, 
-                vk::Fence::null(),
-            ) {
-                Ok((image_index, _)) => image_index,
-                Err(err) => panic!("Error acquiring image: {}", err),
+            // offscreen rendering has no image to acquire, there is only one color image
+            let (framebuffer, image_index, mut renew_swapchain) = match &self.target {
+                RenderTarget::Swapchain(target) => {
+                    match target.swapchain.acquire_next_image(
+                        target.swapchain_khr, 
+                        u64::MAX, 
+                        image_available_semaphore, 
+                        vk::Fence::null(),
+                    ) {
+                        // a suboptimal image is still acquired and can be presented
+                        Ok((image_index, suboptimal)) => (target.framebuffers[image_index as usize].handle(), Some(image_index), suboptimal),
+                        Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                            // the fence is left signaled as nothing gets submitted this frame
+                            self.instanced_draws.clear();
+                            return self.renew_swapchain();
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                RenderTarget::Offscreen(target) => (target.framebuffer.handle(), None, false),
+            };
+
+            self.device.reset_fences(fences)?;
+
+            let indirect_draws = self.indirect_draws
+                .then(|| indirect::IndirectDraws::build(&self.scene, &self.instanced_draws, &self.geometry));
+            let instance_buffer = self.instance_buffer.write(
+                &self.device,
+                &mut self.context.allocator,
+                &self.context.deletion_queue,
+                self.current_frame,
+                match &indirect_draws {
+                    Some(draws) => &draws.instances,
+                    None => &self.instanced_draws.instances,
+                },
+            )?;
+            // the commands only reach the indirect buffer through the culling pass
+            let culled_draws = match &indirect_draws {
+                Some(draws) if !draws.commands.is_empty() => Some(self.culling.prepare(
+                    &self.device,
+                    &mut self.context.allocator,
+                    &self.context.deletion_queue,
+                    self.current_frame,
+                    draws,
+                )?),
+                _ => None,
             };
+            // batches over the limit are drawn in several calls, which the gpu side count can not be split across
+            let compact = self.draw_indirect_count && indirect_draws.as_ref().is_some_and(|draws| {
+                draws.batches.iter().all(|(_, commands)| commands.len() as u32 <= self.max_draw_indirect_count)
+            });
         
             self.device.reset_command_buffer(
                 command_buffer, 
                 vk::CommandBufferResetFlags::empty()
-            ).expect("Failed to reset command buffer contents"); 
+            )?; 
     
+            let mut screenshot_readback = None;
+            // signaled by the culling submitted to the compute queue
+            let mut culling_finished = None;
+
             // record command buffer
             {   
                 let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
-                    .render_pass(self.render_pass)
-                    .framebuffer(self.swapchain_framebuffers[image_index as usize])
+                    .render_pass(self.render_pass.handle())
+                    .framebuffer(framebuffer)
                     .render_area(vk::Rect2D {
                         offset: vk::Offset2D {
                             x: 0, y: 0,
                         },
-                        extent: self.swapchain_extent,
+                        extent: self.extent,
                     })
                     .clear_values(&[
                         vk::ClearValue {
@@ -739,7 +984,29 @@ impl Vulkan {
                 self.device.begin_command_buffer(
                     command_buffer, 
                     &vk::CommandBufferBeginInfo::default()
-                ).expect("Failed to begin recording command buffer");
+                )?;
+
+                // on the compute queue the draws wait for culling with a semaphore instead of a barrier
+                if let (Some(draws), Some(culled_draws)) = (&indirect_draws, &culled_draws) {
+                    let command_count = draws.commands.len() as u32;
+                    match &self.async_compute {
+                        Some(async_compute) => {
+                            let compute_command_buffer = async_compute.begin(&self.device, self.current_frame)?;
+                            self.culling.record(&self.device, compute_command_buffer, self.current_frame, command_count, compact);
+                            culling_finished = Some(async_compute.submit(&self.device, self.current_frame)?);
+                        }
+                        None => {
+                            self.culling.record(&self.device, command_buffer, self.current_frame, command_count, compact);
+                            barrier::buffer_barrier(
+                                &self.device,
+                                command_buffer,
+                                &[culled_draws.indirect_buffer, culled_draws.draw_count_buffer],
+                                barrier::Access::COMPUTE_WRITE,
+                                barrier::Access::INDIRECT_READ,
+                            );
+                        }
+                    }
+                }
     
                 self.device.cmd_begin_render_pass(
                     command_buffer, 
@@ -747,20 +1014,14 @@ impl Vulkan {
                     vk::SubpassContents::INLINE
                 );
 
-                self.device.cmd_bind_pipeline(
-                    command_buffer, 
-                    vk::PipelineBindPoint::GRAPHICS, 
-                    self.pipeline
-                );
-    
                 self.device.cmd_set_viewport(
                     command_buffer, 
                     0, 
                     &[vk::Viewport {
                         x: 0.0, 
                         y: 0.0,
-                        width: self.swapchain_extent.width as f32, 
-                        height: self.swapchain_extent.height as f32,
+                        width: self.extent.width as f32, 
+                        height: self.extent.height as f32,
                         min_depth: 0.0, 
                         max_depth: 1.0, 
                     }]
@@ -773,124 +1034,334 @@ impl Vulkan {
                             x: 0,
                             y: 0,
                         },
-                        extent: self.swapchain_extent,
+                        extent: self.extent,
                     }]
                 );
 
-                self.device.cmd_bind_descriptor_sets(
-                    command_buffer, 
-                    vk::PipelineBindPoint::GRAPHICS, 
-                    self.pipeline_layout, 
-                    0, 
-                    &[self.descriptor_set], 
-                    &[self.camera_buffer_stride as u32 * self.current_frame as u32]
-                );
-                
-                self.device.cmd_bind_index_buffer(
-                    command_buffer, 
-                    self.index_buffer, 
-                    0, 
-                    vk::IndexType::UINT32
-                );
-                self.device.cmd_bind_vertex_buffers(
-                    command_buffer, 
-                    0, 
-                    &[self.vertex_buffer], &[0]);
-
-                self.device.cmd_draw_indexed(command_buffer, self.indices_len, 1, 0, 0, 0);
+                // nothing is drawn until a mesh with any indices was uploaded
+                if let Some((vertex_buffer, index_buffer)) = self.geometry.buffers() {
+                    self.device.cmd_bind_index_buffer(
+                        command_buffer, 
+                        index_buffer, 
+                        0, 
+                        vk::IndexType::UINT32
+                    );
+                    match (&indirect_draws, instance_buffer, &culled_draws) {
+                        (Some(draws), Some(instance_buffer), Some(culled_draws)) => self.record_indirect_draws(
+                            command_buffer,
+                            vertex_buffer,
+                            instance_buffer,
+                            culled_draws,
+                            &draws.batches,
+                            compact,
+                        ),
+                        (Some(_), ..) => {}
+                        (None, ..) => self.record_cpu_draws(command_buffer, vertex_buffer, instance_buffer),
+                    }
+                }
+                self.instanced_draws.clear();
 
                 self.device.cmd_end_render_pass(command_buffer);
+
+                if let (RenderTarget::Swapchain(target), Some(image_index), Some(_)) = (&self.target, image_index, &self.screenshot_path) {
+                    let readback = readback::create_readback_buffer(
+                        &self.device,
+                        &mut self.context.allocator,
+                        &self.context.deletion_queue,
+                        self.extent,
+                        target.surface_format.format,
+                    )?;
+                    readback.record_copy(
+                        &self.device,
+                        command_buffer,
+                        target.images[image_index as usize],
+                        vk::ImageLayout::PRESENT_SRC_KHR,
+                    );
+                    screenshot_readback = Some(readback);
+                }
     
-                self.device.end_command_buffer(command_buffer).expect("Could not end recording command buffer");
+                self.device.end_command_buffer(command_buffer)?;
             }
 
             // render
             {
+                let mut wait_semaphores = Vec::new();
+                let mut wait_stages = Vec::new();
+                if image_index.is_some() {
+                    wait_semaphores.push(image_available_semaphore);
+                    wait_stages.push(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+                }
+                // only the indirect draws wait for culling, the frame's other work overlaps it
+                if let Some(culling_finished) = culling_finished {
+                    wait_semaphores.push(culling_finished);
+                    wait_stages.push(vk::PipelineStageFlags::DRAW_INDIRECT);
+                }
+                let signal_semaphores = [render_finished_semaphore];
+                let signal_count = if image_index.is_some() { 1 } else { 0 };
+
                 let render_info = vk::SubmitInfo::builder()
                     .command_buffers(&[command_buffer])
-                    .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-                    .wait_semaphores(&[image_available_semaphore])
-                    .signal_semaphores(&[render_finished_semaphore])
+                    .wait_dst_stage_mask(&wait_stages)
+                    .wait_semaphores(&wait_semaphores)
+                    .signal_semaphores(&signal_semaphores[..signal_count])
                     .build();
                 let render_infos = [render_info];
     
-                self.device.queue_submit(self.graphics_queue, &render_infos, in_flight_fence).unwrap();
+                self.device.queue_submit(self.graphics_queue, &render_infos, in_flight_fence)?;
             }
 
             //present
-            {
+            if let (RenderTarget::Swapchain(target), Some(image_index)) = (&self.target, image_index) {
                 let present_info = vk::PresentInfoKHR::builder()
                     .wait_semaphores(&[render_finished_semaphore])
-                    .swapchains(&[self.swapchain_khr])
+                    .swapchains(&[target.swapchain_khr])
                     .image_indices(&[image_index])
                     .build();
-                match self.swapchain.queue_present(self.present_queue, &present_info) {
-                    Err(err) => panic!("Error presenting: {}", err),
-                    _ => {},
+                match target.swapchain.queue_present(target.present_queue, &present_info) {
+                    Ok(suboptimal) => renew_swapchain |= suboptimal,
+                    Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => renew_swapchain = true,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+    
+            if let Some(readback) = screenshot_readback {
+                self.device.wait_for_fences(&[in_flight_fence], true, u64::MAX)?;
+                let pixels = readback.read_rgba8()?;
+
+                // the frame itself was rendered and presented, so this does not fail it
+                let path = self.screenshot_path.take().unwrap();
+                if let Err(err) = save_screenshot(&path, readback.extent, pixels) {
+                    log::error!("Could not save screenshot to {}: {}", path.display(), err);
                 }
             }
     
             self.current_frame = (self.current_frame + 1) % FRAMES_IN_FLIGHT as usize;
+
+            if renew_swapchain {
+                self.renew_swapchain()?;
+            }
+
+            Ok(())
         }
     }
-}
-
-impl Drop for Vulkan {
-    fn drop(&mut self) {
-        unsafe {
-            self.device.device_wait_idle().unwrap();
 
-            self.device.destroy_image_view(self.depth_image_view, None);
-            self.device.free_memory(self.depth_image_memory, None);
-            self.device.destroy_image(self.depth_image, None);
+    // one multi draw per texture, or one indirect draw per command without multiDrawIndirect,
+    // compacted batches draw as many commands as culling left in them
+    unsafe fn record_indirect_draws(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        vertex_buffer: vk::Buffer,
+        instance_buffer: vk::Buffer,
+        culled_draws: &culling::CulledDraws,
+        batches: &[(texture::TextureId, std::ops::Range<u32>)],
+        compact: bool,
+    ) {
+        self.device.cmd_bind_pipeline(
+            command_buffer, 
+            vk::PipelineBindPoint::GRAPHICS, 
+            self.instanced_pipeline.handle()
+        );
+        self.device.cmd_bind_vertex_buffers(
+            command_buffer, 
+            0, 
+            &[vertex_buffer, instance_buffer], &[0, 0]);
+
+        let stride = size_of::<indirect::DrawCommand>() as u32;
+        for (batch, (texture, commands)) in batches.iter().enumerate() {
+            self.bind_texture(command_buffer, self.instanced_pipeline_layout.handle(), *texture);
+
+            if compact {
+                self.device.cmd_draw_indexed_indirect_count(
+                    command_buffer,
+                    culled_draws.indirect_buffer,
+                    commands.start as vk::DeviceSize * stride as vk::DeviceSize,
+                    culled_draws.draw_count_buffer,
+                    (batch * size_of::<u32>()) as vk::DeviceSize,
+                    commands.len() as u32,
+                    stride,
+                );
+                continue;
+            }
 
-            self.device.destroy_image_view(self.image_view, None);
-            self.device.destroy_sampler(self.image_sampler, None);
-            
-            self.device.free_memory(self.image_memory, None);
-            self.device.destroy_image(self.image, None);
+            // culled commands are left in place without instances
+            for first in commands.clone().step_by(self.max_draw_indirect_count as usize) {
+                self.device.cmd_draw_indexed_indirect(
+                    command_buffer,
+                    culled_draws.indirect_buffer,
+                    first as vk::DeviceSize * stride as vk::DeviceSize,
+                    (commands.end - first).min(self.max_draw_indirect_count),
+                    stride,
+                );
+            }
+        }
+    }
 
-            self.device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
-            self.device.destroy_descriptor_pool(self.descriptor_pool, None);
+    // the fallback for record_indirect_draws, scene objects get their transform as a push constant,
+    // culled on the cpu with the same test as cull.comp
+    unsafe fn record_cpu_draws(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        vertex_buffer: vk::Buffer,
+        instance_buffer: Option<vk::Buffer>,
+    ) {
+        self.device.cmd_bind_pipeline(
+            command_buffer, 
+            vk::PipelineBindPoint::GRAPHICS, 
+            self.pipeline.handle()
+        );
+        self.device.cmd_bind_vertex_buffers(
+            command_buffer, 
+            0, 
+            &[vertex_buffer], &[0]);
+
+        // only rebound when it changes between consecutive objects
+        let mut bound_texture = None;
+        let planes = &self.culling.frustum_planes;
+        for object in self.scene.objects() {
+            let sphere = culling::transform_sphere(&object.transform, self.geometry.bounding_sphere(object.mesh));
+            if !culling::sphere_visible(planes, sphere) {
+                continue;
+            }
 
-            self.device.unmap_memory(self.camera_memory);
-            self.camera_mapped_ptr = null_mut();
-            self.device.free_memory(self.camera_memory, None);
-            self.device.destroy_buffer(self.camera_buffer, None);  
+            if bound_texture != Some(object.texture) {
+                self.bind_texture(command_buffer, self.pipeline_layout.handle(), object.texture);
+                bound_texture = Some(object.texture);
+            }
 
-            self.device.free_memory(self.index_memory, None);
-            self.device.destroy_buffer(self.index_buffer, None);            
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout.handle(),
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytemuck::bytes_of(&object.transform),
+            );
+            let range = self.geometry.range(object.mesh);
+            self.device.cmd_draw_indexed(command_buffer, range.index_count, 1, range.first_index, range.vertex_offset, 0);
+        }
 
-            self.device.free_memory(self.vertex_memory, None);
-            self.device.destroy_buffer(self.vertex_buffer, None);
+        let Some(instance_buffer) = instance_buffer else {
+            return;
+        };
+        self.device.cmd_bind_pipeline(
+            command_buffer, 
+            vk::PipelineBindPoint::GRAPHICS, 
+            self.instanced_pipeline.handle()
+        );
+        self.device.cmd_bind_vertex_buffers(
+            command_buffer, 
+            0, 
+            &[vertex_buffer, instance_buffer], &[0, 0]);
+
+        // the pipeline layouts differ in their push constants, so the set is bound again
+        let mut bound_texture = None;
+        for draw in &self.instanced_draws.draws {
+            let sphere = culling::instances_sphere(self.geometry.bounding_sphere(draw.mesh), self.instanced_draws.instances(draw));
+            if !culling::sphere_visible(planes, sphere) {
+                continue;
+            }
 
-            for frame in 0..FRAMES_IN_FLIGHT as usize {
-                self.device.destroy_semaphore(self.image_available_semaphores[frame], None);
-                self.device.destroy_semaphore(self.render_finished_semaphores[frame], None);
-                self.device.destroy_fence(self.in_flight_fences[frame], None);
+            if bound_texture != Some(draw.texture) {
+                self.bind_texture(command_buffer, self.instanced_pipeline_layout.handle(), draw.texture);
+                bound_texture = Some(draw.texture);
             }
 
-            self.device.destroy_pipeline(self.pipeline, None);
-            self.device.destroy_pipeline_layout(self.pipeline_layout, None);
+            let range = self.geometry.range(draw.mesh);
+            self.device.cmd_draw_indexed(
+                command_buffer,
+                range.index_count,
+                draw.instance_count,
+                range.first_index,
+                range.vertex_offset,
+                draw.first_instance,
+            );
+        }
+    }
 
-            for i in 0..self.swapchain_image_views.len() {
-                self.device.destroy_framebuffer(self.swapchain_framebuffers[i], None);
-                self.device.destroy_image_view(self.swapchain_image_views[i], None);   
-            }
-            self.swapchain.destroy_swapchain(self.swapchain_khr, None);
+    unsafe fn bind_texture(&self, command_buffer: vk::CommandBuffer, pipeline_layout: vk::PipelineLayout, texture: texture::TextureId) {
+        self.device.cmd_bind_descriptor_sets(
+            command_buffer, 
+            vk::PipelineBindPoint::GRAPHICS, 
+            pipeline_layout, 
+            0, 
+            &[self.textures[texture.0].descriptor_set], 
+            &[self.camera_buffer_stride as u32 * self.current_frame as u32]
+        );
+    }
+
+    // tightly packed RGBA8 rows of the offscreen color image, top row first
+    pub fn read_pixels(&mut self) -> Result<Vec<u8>, RendererError> {
+        let RenderTarget::Offscreen(target) = &self.target else {
+            return Err(RendererError::NotOffscreen);
+        };
 
-            self.device.destroy_render_pass(self.render_pass, None);
+        let readback = readback::create_readback_buffer(
+            &self.device,
+            &mut self.context.allocator,
+            &self.context.deletion_queue,
+            self.extent,
+            target.format,
+        )?;
 
-            self.device.destroy_command_pool(self.command_pool, None);
-            self.device.destroy_command_pool(self.transient_command_pool, None);
+        unsafe {
+            // the last submitted frame has to finish before its pixels can be copied
+            self.device.device_wait_idle()?;
 
-            self.device.destroy_device(None);
+            let command_buffer = command::begin_one_time_commands(&self.device, self.transient_command_pool.handle())?;
+            readback.record_copy(&self.device, command_buffer, target.image.handle(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+            command::end_one_time_commands(&self.device, self.transient_command_pool.handle(), self.graphics_queue, command_buffer)?;
 
-            self.surface.destroy_surface(self.surface_khr, None);
+            readback.read_rgba8()
+        }
+    }
 
-            self.debug_utils.destroy_debug_utils_messenger(self.debug_messenger, None);
+    // presented swapchain images may not be touched until they are acquired again,
+    // so the copy is recorded into the next frame and saved once that frame finishes
+    pub fn capture_screenshot(&mut self, path: impl AsRef<Path>) -> Result<(), RendererError> {
+        match &self.target {
+            RenderTarget::Swapchain(_) => self.screenshot_path = Some(path.as_ref().to_path_buf()),
+            RenderTarget::Offscreen(_) => {
+                let pixels = self.read_pixels()?;
+                save_screenshot(path.as_ref(), self.extent, pixels)?;
+            }
+        }
+        Ok(())
+    }
+}
 
-            self.instance.destroy_instance(None);
+fn save_screenshot(path: &Path, extent: vk::Extent2D, pixels: Vec<u8>) -> Result<(), RendererError> {
+    let image = image::RgbaImage::from_raw(extent.width, extent.height, pixels).ok_or_else(|| {
+        RendererError::Image(image::ImageError::Parameter(image::error::ParameterError::from_kind(
+            image::error::ParameterErrorKind::DimensionMismatch,
+        )))
+    })?;
+    image.save(path).map_err(|err| match err {
+        image::ImageError::IoError(source) => RendererError::Io {
+            path: path.to_path_buf(),
+            source,
+        },
+        err => RendererError::Image(err),
+    })?;
+    log::info!("Saved screenshot to {}", path.display());
+    Ok(())
+}
+
+impl Drop for Vulkan {
+    // the fields queue their handles as they are dropped once the device is idle,
+    // and context destroys them last
+    fn drop(&mut self) {
+        unsafe {
+            // destruction goes ahead regardless, e.g. after the device was lost
+            if let Err(err) = self.device.device_wait_idle() {
+                log::error!("Error waiting for device idle: {}", err);
+            }
+
+            self.camera_mapped_ptr = null_mut();
+
+            if let Some(path) = &self.pipeline_cache_path {
+                if let Err(err) = pipeline_cache::save_pipeline_cache(&self.device, self.context.pipeline_cache, path) {
+                    log::error!("Could not save pipeline cache: {}", err);
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
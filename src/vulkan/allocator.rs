@@ -0,0 +1,377 @@
+use std::ptr::null_mut;
+
+use ash::vk;
+
+use super::{device::find_memory_type_index, RendererError};
+
+// sub-allocates buffers and images out of large device memory blocks,
+// so the number of vkAllocateMemory calls stays far below maxMemoryAllocationCount
+
+const MAX_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+// heaps at most this big get blocks of an eighth of their size instead
+const SMALL_HEAP_SIZE: vk::DeviceSize = 1024 * 1024 * 1024;
+
+// bufferImageGranularity only separates linear resources (buffers, linear images)
+// from optimal tiling images, neighbours of the same kind may share a page
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    Linear,
+    Optimal,
+}
+
+impl ResourceKind {
+    pub fn from_tiling(tiling: vk::ImageTiling) -> Self {
+        match tiling {
+            vk::ImageTiling::OPTIMAL => Self::Optimal,
+            _ => Self::Linear,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Suballocation {
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    kind: ResourceKind,
+}
+
+// bookkeeping of a single block, free ranges are the gaps between the live suballocations
+#[derive(Debug)]
+pub struct Block {
+    size: vk::DeviceSize,
+    granularity: vk::DeviceSize,
+    // sorted by offset
+    suballocations: Vec<Suballocation>,
+}
+
+impl Block {
+    pub fn new(size: vk::DeviceSize, granularity: vk::DeviceSize) -> Self {
+        Self {
+            size,
+            granularity: granularity.max(1),
+            suballocations: Vec::new(),
+        }
+    }
+
+    // first fit, returns the offset of the new suballocation
+    pub fn allocate(&mut self, size: vk::DeviceSize, alignment: vk::DeviceSize, kind: ResourceKind) -> Option<vk::DeviceSize> {
+        debug_assert!(size > 0);
+
+        for i in 0..=self.suballocations.len() {
+            let prev = i.checked_sub(1).map(|i| self.suballocations[i]);
+            let next = self.suballocations.get(i).copied();
+
+            let gap_start = prev.map_or(0, |prev| prev.offset + prev.size);
+            let gap_end = next.map_or(self.size, |next| next.offset);
+
+            let mut offset = align_up(gap_start, alignment);
+            if let Some(prev) = prev {
+                if prev.kind != kind && self.same_page(prev.offset + prev.size - 1, offset) {
+                    offset = align_up(offset, self.granularity);
+                }
+            }
+
+            let end = offset.checked_add(size)?;
+            if end > gap_end {
+                continue;
+            }
+            if let Some(next) = next {
+                if next.kind != kind && self.same_page(end - 1, next.offset) {
+                    continue;
+                }
+            }
+
+            self.suballocations.insert(i, Suballocation { offset, size, kind });
+            return Some(offset);
+        }
+
+        None
+    }
+
+    pub fn free(&mut self, offset: vk::DeviceSize) {
+        let i = self.suballocations
+            .binary_search_by_key(&offset, |suballocation| suballocation.offset)
+            .expect("freed offset was never allocated from this block");
+        self.suballocations.remove(i);
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn used(&self) -> vk::DeviceSize {
+        self.suballocations.iter().map(|suballocation| suballocation.size).sum()
+    }
+
+    pub fn allocation_count(&self) -> usize {
+        self.suballocations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.suballocations.is_empty()
+    }
+
+    fn same_page(&self, a: vk::DeviceSize, b: vk::DeviceSize) -> bool {
+        a / self.granularity == b / self.granularity
+    }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    let alignment = alignment.max(1);
+    value.div_ceil(alignment) * alignment
+}
+
+struct MemoryBlock {
+    memory: vk::DeviceMemory,
+    // the whole block stays mapped for host visible types, null otherwise
+    mapped_ptr: *mut u8,
+    block: Block,
+}
+
+pub struct Allocation {
+    memory: vk::DeviceMemory,
+    memory_type_index: u32,
+    offset: vk::DeviceSize,
+    mapped_ptr: *mut u8,
+}
+
+impl Allocation {
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    pub fn offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+
+    // start of this allocation in the persistently mapped block,
+    // None unless the memory type is host visible
+    pub fn mapped_ptr(&self) -> Option<*mut u8> {
+        (!self.mapped_ptr.is_null()).then_some(self.mapped_ptr)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapStats {
+    pub block_count: usize,
+    pub allocation_count: usize,
+    // bytes allocated from the device
+    pub block_bytes: vk::DeviceSize,
+    // bytes handed out to resources, excluding alignment padding
+    pub used_bytes: vk::DeviceSize,
+}
+
+pub struct Allocator {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: vk::DeviceSize,
+    // indexed by memory type
+    blocks: Vec<Vec<MemoryBlock>>,
+}
+
+impl Allocator {
+    pub fn new(memory_properties: vk::PhysicalDeviceMemoryProperties, limits: &vk::PhysicalDeviceLimits) -> Self {
+        Self {
+            memory_properties,
+            buffer_image_granularity: limits.buffer_image_granularity,
+            blocks: (0..memory_properties.memory_type_count).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        device: &ash::Device,
+        requirements: vk::MemoryRequirements,
+        required_properties: vk::MemoryPropertyFlags,
+        kind: ResourceKind,
+    ) -> Result<Allocation, RendererError> {
+        let memory_type_index = find_memory_type_index(
+            requirements.memory_type_bits,
+            required_properties,
+            &self.memory_properties,
+        )?;
+        let blocks = &mut self.blocks[memory_type_index as usize];
+
+        for block in blocks.iter_mut() {
+            if let Some(offset) = block.block.allocate(requirements.size, requirements.alignment, kind) {
+                return Ok(block_allocation(block, memory_type_index, offset));
+            }
+        }
+
+        // resources bigger than a block get a block of their own
+        let memory_type = self.memory_properties.memory_types[memory_type_index as usize];
+        let heap_size = self.memory_properties.memory_heaps[memory_type.heap_index as usize].size;
+        let block_size = preferred_block_size(heap_size).max(requirements.size);
+
+        let memory = unsafe {
+            device.allocate_memory(
+                &vk::MemoryAllocateInfo::builder()
+                    .allocation_size(block_size)
+                    .memory_type_index(memory_type_index),
+                None,
+            )?
+        };
+        let mapped_ptr = if memory_type.property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            match unsafe { device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()) } {
+                Ok(ptr) => ptr as *mut u8,
+                Err(err) => {
+                    unsafe { device.free_memory(memory, None) };
+                    return Err(err.into());
+                }
+            }
+        } else {
+            null_mut()
+        };
+
+        let mut block = MemoryBlock {
+            memory,
+            mapped_ptr,
+            block: Block::new(block_size, self.buffer_image_granularity),
+        };
+        let offset = block.block
+            .allocate(requirements.size, requirements.alignment, kind)
+            .expect("a fresh block fits the allocation");
+        let allocation = block_allocation(&block, memory_type_index, offset);
+        blocks.push(block);
+
+        Ok(allocation)
+    }
+
+    // the resource bound to the allocation must be destroyed or no longer in use,
+    // empty blocks are given back to the device
+    pub unsafe fn free(&mut self, device: &ash::Device, allocation: &Allocation) {
+        let blocks = &mut self.blocks[allocation.memory_type_index as usize];
+        let i = blocks
+            .iter()
+            .position(|block| block.memory == allocation.memory)
+            .expect("allocation was not made by this allocator");
+
+        blocks[i].block.free(allocation.offset);
+        if blocks[i].block.is_empty() {
+            let block = blocks.swap_remove(i);
+            device.free_memory(block.memory, None);
+        }
+    }
+
+    // indexed by memory heap
+    pub fn stats(&self) -> Vec<HeapStats> {
+        let mut stats = vec![HeapStats::default(); self.memory_properties.memory_heap_count as usize];
+        for (memory_type_index, blocks) in self.blocks.iter().enumerate() {
+            let heap_index = self.memory_properties.memory_types[memory_type_index].heap_index as usize;
+            for block in blocks {
+                let heap = &mut stats[heap_index];
+                heap.block_count += 1;
+                heap.allocation_count += block.block.allocation_count();
+                heap.block_bytes += block.block.size();
+                heap.used_bytes += block.block.used();
+            }
+        }
+        stats
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for (heap_index, heap) in self.stats().iter().enumerate() {
+            if heap.allocation_count > 0 {
+                log::warn!("{} allocations still alive in heap {} when destroying the allocator", heap.allocation_count, heap_index);
+            }
+        }
+
+        for block in self.blocks.iter_mut().flat_map(|blocks| blocks.drain(..)) {
+            device.free_memory(block.memory, None);
+        }
+    }
+}
+
+fn block_allocation(block: &MemoryBlock, memory_type_index: u32, offset: vk::DeviceSize) -> Allocation {
+    Allocation {
+        memory: block.memory,
+        memory_type_index,
+        offset,
+        mapped_ptr: if block.mapped_ptr.is_null() {
+            null_mut()
+        } else {
+            unsafe { block.mapped_ptr.add(offset as usize) }
+        },
+    }
+}
+
+fn preferred_block_size(heap_size: vk::DeviceSize) -> vk::DeviceSize {
+    if heap_size <= SMALL_HEAP_SIZE {
+        heap_size / 8
+    } else {
+        MAX_BLOCK_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_aligned_and_packed() {
+        let mut block = Block::new(1024, 1);
+        assert_eq!(block.allocate(10, 1, ResourceKind::Linear), Some(0));
+        assert_eq!(block.allocate(16, 16, ResourceKind::Linear), Some(16));
+        assert_eq!(block.allocate(4, 4, ResourceKind::Linear), Some(12));
+        assert_eq!(block.used(), 30);
+        assert_eq!(block.allocation_count(), 3);
+    }
+
+    #[test]
+    fn full_block_refuses_allocation() {
+        let mut block = Block::new(256, 1);
+        assert_eq!(block.allocate(200, 1, ResourceKind::Linear), Some(0));
+        assert_eq!(block.allocate(64, 1, ResourceKind::Linear), None);
+        assert_eq!(block.allocate(56, 1, ResourceKind::Linear), Some(200));
+        assert_eq!(block.allocate(1, 1, ResourceKind::Linear), None);
+    }
+
+    #[test]
+    fn freed_ranges_are_reused() {
+        let mut block = Block::new(300, 1);
+        let a = block.allocate(100, 1, ResourceKind::Linear).unwrap();
+        let b = block.allocate(100, 1, ResourceKind::Linear).unwrap();
+        let c = block.allocate(100, 1, ResourceKind::Linear).unwrap();
+
+        block.free(b);
+        assert_eq!(block.allocate(101, 1, ResourceKind::Linear), None);
+        assert_eq!(block.allocate(100, 1, ResourceKind::Linear), Some(b));
+
+        block.free(a);
+        block.free(b);
+        block.free(c);
+        assert!(block.is_empty());
+        assert_eq!(block.allocate(300, 1, ResourceKind::Linear), Some(0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn freeing_unknown_offset_panics() {
+        let mut block = Block::new(64, 1);
+        block.allocate(16, 1, ResourceKind::Linear);
+        block.free(8);
+    }
+
+    #[test]
+    fn granularity_separates_linear_and_optimal() {
+        let mut block = Block::new(4096, 1024);
+        assert_eq!(block.allocate(100, 4, ResourceKind::Linear), Some(0));
+        assert_eq!(block.allocate(100, 4, ResourceKind::Optimal), Some(1024));
+        // same kind may share the page
+        assert_eq!(block.allocate(100, 4, ResourceKind::Optimal), Some(1124));
+        // the rest of the first page is still usable by linear resources
+        assert_eq!(block.allocate(100, 4, ResourceKind::Linear), Some(100));
+        assert_eq!(block.allocate(900, 4, ResourceKind::Linear), Some(2048));
+    }
+
+    #[test]
+    fn granularity_applies_to_following_neighbour() {
+        let mut block = Block::new(4096, 1024);
+        let a = block.allocate(100, 1, ResourceKind::Optimal).unwrap();
+        assert_eq!(block.allocate(100, 1, ResourceKind::Optimal), Some(100));
+        block.free(a);
+
+        // would end on the page the optimal image starts on
+        assert_eq!(block.allocate(50, 1, ResourceKind::Linear), Some(1024));
+        assert_eq!(block.allocate(50, 1, ResourceKind::Optimal), Some(0));
+    }
+}
@@ -0,0 +1,83 @@
+use ash::vk;
+
+use super::{resource::{CommandPool, DeletionQueue, Semaphore}, RendererError, FRAMES_IN_FLIGHT};
+
+// compute work recorded on a queue family without graphics, overlapping the previous frame's
+// rendering, the frame's graphics submission waits on its semaphore, see Config::async_compute
+pub struct AsyncCompute {
+    pub queue: vk::Queue,
+    pub family_index: u32,
+    // frees the command buffers when dropped
+    _command_pool: CommandPool,
+    command_buffers: Vec<vk::CommandBuffer>,
+    finished_semaphores: Vec<Semaphore>,
+}
+
+impl AsyncCompute {
+    pub fn new(
+        device: &ash::Device,
+        deletion_queue: &DeletionQueue,
+        queue: vk::Queue,
+        family_index: u32,
+    ) -> Result<Self, RendererError> {
+        unsafe {
+            let command_pool = CommandPool::new(device.create_command_pool(
+                &vk::CommandPoolCreateInfo::builder()
+                    .queue_family_index(family_index)
+                    .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER),
+                None,
+            )?, deletion_queue);
+            let command_buffers = device.allocate_command_buffers(
+                &vk::CommandBufferAllocateInfo::builder()
+                    .command_pool(command_pool.handle())
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(FRAMES_IN_FLIGHT as u32),
+            )?;
+
+            let mut finished_semaphores = Vec::with_capacity(FRAMES_IN_FLIGHT as usize);
+            for _ in 0..FRAMES_IN_FLIGHT {
+                finished_semaphores.push(Semaphore::new(
+                    device.create_semaphore(&vk::SemaphoreCreateInfo::builder(), None)?,
+                    deletion_queue,
+                ));
+            }
+
+            Ok(Self {
+                queue,
+                family_index,
+                _command_pool: command_pool,
+                command_buffers,
+                finished_semaphores,
+            })
+        }
+    }
+
+    // the frame's command buffer, reset and begun, to be called once the frame's fence was waited on
+    // as the graphics submission waiting for its previous use has finished by then
+    pub unsafe fn begin(&self, device: &ash::Device, frame: usize) -> Result<vk::CommandBuffer, RendererError> {
+        let command_buffer = self.command_buffers[frame];
+        device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+        device.begin_command_buffer(
+            command_buffer,
+            &vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+        )?;
+        Ok(command_buffer)
+    }
+
+    // returns the semaphore signaled once the work has finished, to be waited on by exactly one submission
+    pub unsafe fn submit(&self, device: &ash::Device, frame: usize) -> Result<vk::Semaphore, RendererError> {
+        let command_buffer = self.command_buffers[frame];
+        device.end_command_buffer(command_buffer)?;
+
+        let finished_semaphore = self.finished_semaphores[frame].handle();
+        device.queue_submit(
+            self.queue,
+            &[vk::SubmitInfo::builder()
+                .command_buffers(&[command_buffer])
+                .signal_semaphores(&[finished_semaphore])
+                .build()],
+            vk::Fence::null(),
+        )?;
+        Ok(finished_semaphore)
+    }
+}
@@ -0,0 +1,91 @@
+use ash::vk;
+
+// a stage and what it does to a resource, either side of a barrier
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub stage: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+}
+
+impl Access {
+    // the source of a barrier whose contents are discarded, e.g. from UNDEFINED
+    pub const NONE_BEFORE: Self = Self::new(vk::PipelineStageFlags::TOP_OF_PIPE, vk::AccessFlags::empty());
+    pub const COMPUTE_WRITE: Self = Self::new(vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE);
+    pub const INDIRECT_READ: Self = Self::new(vk::PipelineStageFlags::DRAW_INDIRECT, vk::AccessFlags::INDIRECT_COMMAND_READ);
+    pub const COLOR_ATTACHMENT_WRITE: Self = Self::new(
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+    );
+    pub const FRAGMENT_SHADER_READ: Self = Self::new(vk::PipelineStageFlags::FRAGMENT_SHADER, vk::AccessFlags::SHADER_READ);
+    pub const TRANSFER_READ: Self = Self::new(vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ);
+    pub const TRANSFER_WRITE: Self = Self::new(vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+    // the destination of a barrier only transitioning the layout for later uses
+    pub const NONE_AFTER: Self = Self::new(vk::PipelineStageFlags::BOTTOM_OF_PIPE, vk::AccessFlags::empty());
+
+    pub const fn new(stage: vk::PipelineStageFlags, access: vk::AccessFlags) -> Self {
+        Self { stage, access }
+    }
+}
+
+// the whole buffers, e.g. between the compute pass writing draws and the draws reading them,
+// barriers are within one queue, buffers shared with the async compute queue are created concurrent
+// instead of being transferred between the families, see buffer::PerFrameBuffer::concurrent
+pub unsafe fn buffer_barrier(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    buffers: &[vk::Buffer],
+    src: Access,
+    dst: Access,
+) {
+    let barriers: Vec<_> = buffers
+        .iter()
+        .map(|&buffer| vk::BufferMemoryBarrier::builder()
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .src_access_mask(src.access)
+            .dst_access_mask(dst.access)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .build())
+        .collect();
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        src.stage,
+        dst.stage,
+        vk::DependencyFlags::empty(),
+        &[],
+        &barriers,
+        &[],
+    );
+}
+
+// also transitions the layout of `subresource_range`, pass the same layout twice to keep it
+pub unsafe fn image_barrier(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    subresource_range: vk::ImageSubresourceRange,
+    (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+    src: Access,
+    dst: Access,
+) {
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        src.stage,
+        dst.stage,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &[vk::ImageMemoryBarrier::builder()
+            .image(image)
+            .subresource_range(subresource_range)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src.access)
+            .dst_access_mask(dst.access)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .build()],
+    );
+}
@@ -1,43 +1,147 @@
-use std::mem::size_of;
-
 use ash::vk;
 
-use super::device::find_memory_type_index;
+use super::{allocator::{Allocator, ResourceKind}, resource::{Buffer, DeletionQueue}, RendererError};
 
 pub fn create_buffer(
     device: &ash::Device,
-    available_properties: &vk::PhysicalDeviceMemoryProperties, 
+    allocator: &mut Allocator,
+    deletion_queue: &DeletionQueue,
     required_properties: vk::MemoryPropertyFlags,
     usage: vk::BufferUsageFlags, 
     size: vk::DeviceSize,
-) -> (vk::Buffer, vk::DeviceMemory) {
+) -> Result<Buffer, RendererError> {
+    create_shared_buffer(device, allocator, deletion_queue, required_properties, usage, size, &[])
+}
+
+// used by the queues of `queue_family_indices` without ownership transfers,
+// exclusive to whichever queue uses it first when there are fewer than two
+pub fn create_shared_buffer(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    deletion_queue: &DeletionQueue,
+    required_properties: vk::MemoryPropertyFlags,
+    usage: vk::BufferUsageFlags, 
+    size: vk::DeviceSize,
+    queue_family_indices: &[u32],
+) -> Result<Buffer, RendererError> {
+    // concurrent sharing requires distinct queue families
+    let queue_family_indices = if queue_family_indices.len() > 1 { queue_family_indices } else { &[] };
+    let sharing_mode = if queue_family_indices.is_empty() {
+        vk::SharingMode::EXCLUSIVE
+    } else {
+        vk::SharingMode::CONCURRENT
+    };
+
     unsafe {
         let buffer = device.create_buffer(
             &vk::BufferCreateInfo::builder()
                 .usage(usage)
                 .size(size)
-                .sharing_mode(vk::SharingMode::EXCLUSIVE), 
+                .sharing_mode(sharing_mode)
+                .queue_family_indices(queue_family_indices),
             None,
-        ).unwrap();
+        )?;
 
         let memory_requirements = device.get_buffer_memory_requirements(buffer);
 
-        let memory_type_index = find_memory_type_index(
-            memory_requirements.memory_type_bits,
+        let allocation = match allocator.allocate(
+            device,
+            memory_requirements,
             required_properties,
-            &available_properties,
-        );
+            ResourceKind::Linear,
+        ) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                device.destroy_buffer(buffer, None);
+                return Err(err);
+            }
+        };
+        // from here on dropping the buffer releases both
+        let buffer = Buffer::new(buffer, allocation, deletion_queue);
 
-        let memory = device.allocate_memory(
-            &vk::MemoryAllocateInfo::builder()
-                .allocation_size(memory_requirements.size)
-                .memory_type_index(memory_type_index)
-                .build(), 
-            None
-        ).unwrap();
+        device.bind_buffer_memory(buffer.handle(), buffer.allocation().memory(), buffer.allocation().offset())?;
+
+        Ok(buffer)
+    }
+}
 
-        device.bind_buffer_memory(buffer, memory, 0).unwrap();
+// a host visible buffer per frame in flight for data rewritten every frame,
+// replaced by a larger one when the data outgrows it
+pub struct PerFrameBuffer {
+    usage: vk::BufferUsageFlags,
+    // see create_shared_buffer
+    queue_family_indices: Vec<u32>,
+    // with their size in bytes
+    buffers: [Option<(Buffer, vk::DeviceSize)>; super::FRAMES_IN_FLIGHT as usize],
+}
+
+impl PerFrameBuffer {
+    // the smallest buffer, so that small amounts of data do not grow it step by step
+    const MIN_SIZE: vk::DeviceSize = 4096;
+
+    pub fn new(usage: vk::BufferUsageFlags) -> Self {
+        Self {
+            usage,
+            queue_family_indices: Vec::new(),
+            buffers: Default::default(),
+        }
+    }
+
+    // for buffers used by more than one queue family, e.g. written by async compute and read by graphics
+    pub fn concurrent(mut self, queue_family_indices: &[u32]) -> Self {
+        self.queue_family_indices = queue_family_indices.to_vec();
+        self
+    }
+
+    // to be called once the previous use of the frame's buffer has finished,
+    // returns None when `data` is empty
+    pub fn write<T: bytemuck::Pod>(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        deletion_queue: &DeletionQueue,
+        frame: usize,
+        data: &[T],
+    ) -> Result<Option<vk::Buffer>, RendererError> {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+
+        let handle = self.reserve(device, allocator, deletion_queue, frame, bytes.len() as vk::DeviceSize)?;
+        let (buffer, _) = self.buffers[frame].as_ref().unwrap();
+        unsafe {
+            buffer.allocation().mapped_ptr().unwrap().copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        }
+        Ok(Some(handle))
+    }
 
-        (buffer, memory)
+    // the frame's buffer with room for at least `size` bytes, for data written by the gpu
+    pub fn reserve(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        deletion_queue: &DeletionQueue,
+        frame: usize,
+        size: vk::DeviceSize,
+    ) -> Result<vk::Buffer, RendererError> {
+        let buffer = &mut self.buffers[frame];
+        if buffer.as_ref().is_none_or(|&(_, capacity)| capacity < size) {
+            let capacity = size.next_power_of_two().max(Self::MIN_SIZE);
+            // the replaced buffer goes to the deletion queue
+            *buffer = Some((
+                create_shared_buffer(
+                    device,
+                    allocator,
+                    deletion_queue,
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    self.usage,
+                    capacity,
+                    &self.queue_family_indices,
+                )?,
+                capacity,
+            ));
+        }
+        Ok(buffer.as_ref().unwrap().0.handle())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,50 @@
+use ash::vk;
+
+use super::RendererError;
+
+// for short lived work like uploads and readbacks,
+// submitted and waited on immediately
+pub fn begin_one_time_commands(
+    device: &ash::Device,
+    transient_command_pool: vk::CommandPool,
+) -> Result<vk::CommandBuffer, RendererError> {
+    unsafe {
+        let command_buffer = device.allocate_command_buffers(
+            &vk::CommandBufferAllocateInfo::builder()
+                .command_pool(transient_command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1)
+        )?[0];
+
+        device.begin_command_buffer(
+            command_buffer,
+            &vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+        )?;
+
+        Ok(command_buffer)
+    }
+}
+
+pub fn end_one_time_commands(
+    device: &ash::Device,
+    transient_command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    command_buffer: vk::CommandBuffer,
+) -> Result<(), RendererError> {
+    unsafe {
+        device.end_command_buffer(command_buffer)?;
+
+        device.queue_submit(
+            queue,
+            &[vk::SubmitInfo::builder()
+                .command_buffers(&[command_buffer])
+                .build()],
+            vk::Fence::null()
+        )?;
+        device.queue_wait_idle(queue)?;
+
+        device.free_command_buffers(transient_command_pool, &[command_buffer]);
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use super::device::DevicePreference;
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub device: Option<DevicePreference>,
+    // pipelines are built from an empty cache and nothing is saved when None
+    pub pipeline_cache_path: Option<PathBuf>,
+    // directory or archive to read assets from, see Assets::new for where they are looked for otherwise
+    pub asset_root: Option<PathBuf>,
+    // one cmd_draw_indexed per draw instead of indirect draws,
+    // also used when the device lacks drawIndirectFirstInstance
+    pub cpu_draws: bool,
+    // culls on a compute only queue family when the device has one,
+    // only used with indirect draws
+    pub async_compute: bool,
+}
+
+impl Config {
+    // ASH_LEARN_DEVICE: physical device index or name substring
+    // ASH_LEARN_PIPELINE_CACHE: pipeline cache file, pipeline_cache.bin by default, empty to disable
+    // ASH_LEARN_ASSETS: asset directory or archive
+    // ASH_LEARN_CPU_DRAWS: 1 to draw without indirect draws
+    // ASH_LEARN_ASYNC_COMPUTE: 1 to cull on a compute queue
+    pub fn from_env() -> Self {
+        Self {
+            device: std::env::var("ASH_LEARN_DEVICE")
+                .ok()
+                .map(|value| DevicePreference::parse(&value)),
+            pipeline_cache_path: match std::env::var_os("ASH_LEARN_PIPELINE_CACHE") {
+                Some(value) if value.is_empty() => None,
+                Some(value) => Some(PathBuf::from(value)),
+                None => Some(PathBuf::from("pipeline_cache.bin")),
+            },
+            asset_root: std::env::var_os("ASH_LEARN_ASSETS").map(PathBuf::from),
+            cpu_draws: std::env::var("ASH_LEARN_CPU_DRAWS").is_ok_and(|value| value == "1"),
+            async_compute: std::env::var("ASH_LEARN_ASYNC_COMPUTE").is_ok_and(|value| value == "1"),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::mem::size_of;
+
+use ash::vk;
+
+use crate::{instance::Instance, math::{Affine3, Vector3}};
+
+use super::{
+    allocator::Allocator,
+    buffer::PerFrameBuffer,
+    indirect::{DrawCommand, IndirectDraws},
+    pipeline::{ComputePipeline, ComputePipelineBuilder},
+    resource::{DeletionQueue, DescriptorPool, DescriptorSetLayout},
+    shaders::{self, ShaderCompiler},
+    RendererError,
+    FRAMES_IN_FLIGHT,
+};
+
+// local_size_x of cull.comp, one command per invocation
+const WORKGROUP_SIZE: u32 = 64;
+
+// see cull.comp
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct CullCommand {
+    // world space center and radius
+    pub sphere: [f32; 4],
+    pub command: DrawCommand,
+    // the batch's draw count, and where its commands start in the output
+    pub batch: u32,
+    pub batch_start: u32,
+    // std430 rounds the struct up to the alignment of the vec4
+    pub _padding: u32,
+}
+
+// cull.comp's push constants
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct CullParams {
+    pub planes: [[f32; 4]; 5],
+    pub command_count: u32,
+    // 0 leaves culled commands in place with no instances instead of compacting them,
+    // for devices without drawIndirectCount
+    pub compact: u32,
+}
+
+// the compute pass writing the indirect draws of a frame, leaving out those outside the frustum
+pub struct Culling {
+    pub pipeline: ComputePipeline,
+    descriptor_set_layout: DescriptorSetLayout,
+    // the pool is only kept alive for the sets
+    _descriptor_pool: DescriptorPool,
+    // one per frame in flight, pointed at the frame's buffers every frame as they may be replaced
+    descriptor_sets: Vec<vk::DescriptorSet>,
+
+    commands_buffer: PerFrameBuffer,
+    indirect_buffer: PerFrameBuffer,
+    draw_count_buffer: PerFrameBuffer,
+
+    // all zero, so nothing is culled, until the first Vulkan::update_camera
+    pub frustum_planes: [[f32; 4]; 5],
+    // keeps frustum_planes while the camera moves, to look at what was culled
+    pub frozen: bool,
+}
+
+// the buffers cull.comp writes this frame
+pub struct CulledDraws {
+    pub indirect_buffer: vk::Buffer,
+    // one count per batch, only written when compacting
+    pub draw_count_buffer: vk::Buffer,
+}
+
+impl Culling {
+    // the output is shared by `queue_family_indices`, the graphics and async compute families
+    // when culling runs on another queue than the draws
+    pub fn new(
+        device: &ash::Device,
+        deletion_queue: &DeletionQueue,
+        shader_compiler: &mut ShaderCompiler,
+        pipeline_cache: vk::PipelineCache,
+        queue_family_indices: &[u32],
+    ) -> Result<Self, RendererError> {
+        let bindings = ComputePipelineBuilder::new(shaders::CULL_COMP)
+            .reflect(shader_compiler)?
+            .sets
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let descriptor_set_layout = DescriptorSetLayout::new(
+            unsafe {
+                device.create_descriptor_set_layout(
+                    &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+                    None,
+                )?
+            },
+            &bindings,
+            deletion_queue,
+        );
+
+        let descriptor_pool = DescriptorPool::new(unsafe { device.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::builder()
+                .max_sets(FRAMES_IN_FLIGHT as u32)
+                .pool_sizes(&bindings
+                    .iter()
+                    .map(|binding| vk::DescriptorPoolSize {
+                        ty: binding.descriptor_type,
+                        descriptor_count: binding.descriptor_count * FRAMES_IN_FLIGHT as u32,
+                    })
+                    .collect::<Vec<_>>()),
+            None,
+        )? }, deletion_queue);
+        let descriptor_sets = unsafe { device.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool.handle())
+                .set_layouts(&[descriptor_set_layout.handle(); FRAMES_IN_FLIGHT as usize]),
+        )? };
+
+        let pipeline = pipeline_builder(&descriptor_set_layout).build(device, deletion_queue, shader_compiler, pipeline_cache)?;
+
+        let indirect_usage = vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER;
+        Ok(Self {
+            pipeline,
+            descriptor_set_layout,
+            _descriptor_pool: descriptor_pool,
+            descriptor_sets,
+            // only ever used by the queue culling runs on
+            commands_buffer: PerFrameBuffer::new(vk::BufferUsageFlags::STORAGE_BUFFER),
+            indirect_buffer: PerFrameBuffer::new(indirect_usage).concurrent(queue_family_indices),
+            draw_count_buffer: PerFrameBuffer::new(indirect_usage).concurrent(queue_family_indices),
+            frustum_planes: [[0.0; 4]; 5],
+            frozen: false,
+        })
+    }
+
+    // to rebuild the pipeline when cull.comp changes
+    pub fn pipeline_builder(&self) -> ComputePipelineBuilder {
+        pipeline_builder(&self.descriptor_set_layout)
+    }
+
+    // to be called once the frame's previous submission has finished, `draws` must have commands
+    pub fn prepare(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        deletion_queue: &DeletionQueue,
+        frame: usize,
+        draws: &IndirectDraws,
+    ) -> Result<CulledDraws, RendererError> {
+        let mut commands = Vec::with_capacity(draws.commands.len());
+        for (batch, (_, range)) in draws.batches.iter().enumerate() {
+            for index in range.clone() {
+                commands.push(CullCommand {
+                    sphere: draws.spheres[index as usize],
+                    command: draws.commands[index as usize],
+                    batch: batch as u32,
+                    batch_start: range.start,
+                    _padding: 0,
+                });
+            }
+        }
+
+        let commands_buffer = self.commands_buffer.write(device, allocator, deletion_queue, frame, &commands)?.unwrap();
+        let indirect_buffer = self.indirect_buffer.reserve(
+            device,
+            allocator,
+            deletion_queue,
+            frame,
+            (commands.len() * size_of::<DrawCommand>()) as vk::DeviceSize,
+        )?;
+        let draw_count_buffer = self.draw_count_buffer
+            .write(device, allocator, deletion_queue, frame, &vec![0u32; draws.batches.len()])?
+            .unwrap();
+
+        let buffer_infos = [commands_buffer, indirect_buffer, draw_count_buffer].map(|buffer| [vk::DescriptorBufferInfo {
+            buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }]);
+        let writes: Vec<_> = buffer_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, info)| vk::WriteDescriptorSet::builder()
+                .dst_set(self.descriptor_sets[frame])
+                .dst_binding(binding as u32)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(info)
+                .build())
+            .collect();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        Ok(CulledDraws {
+            indirect_buffer,
+            draw_count_buffer,
+        })
+    }
+
+    // outside a render pass, the draws reading the output need a barrier after it on the same queue,
+    // see barrier, or to wait for it at the DRAW_INDIRECT stage on another one
+    pub unsafe fn record(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        command_count: u32,
+        compact: bool,
+    ) {
+        self.pipeline.bind(device, command_buffer, &[self.descriptor_sets[frame]]);
+        self.pipeline.push_constants(device, command_buffer, &CullParams {
+            planes: self.frustum_planes,
+            command_count,
+            compact: compact as u32,
+        });
+        self.pipeline.dispatch_invocations(device, command_buffer, [command_count, 1, 1]);
+    }
+}
+
+fn pipeline_builder(descriptor_set_layout: &DescriptorSetLayout) -> ComputePipelineBuilder {
+    ComputePipelineBuilder::new(shaders::CULL_COMP)
+        .set_layouts(&[descriptor_set_layout])
+        .specialize(0, WORKGROUP_SIZE)
+        .local_size([WORKGROUP_SIZE, 1, 1])
+}
+
+// the same test as cull.comp, planes as returned by CameraRender::frustum_planes
+pub fn sphere_visible(planes: &[[f32; 4]; 5], [x, y, z, radius]: [f32; 4]) -> bool {
+    planes.iter().all(|[nx, ny, nz, d]| nx * x + ny * y + nz * z + d >= -radius)
+}
+
+pub fn transform_sphere(transform: &Affine3, [x, y, z, radius]: [f32; 4]) -> [f32; 4] {
+    let center = Vector3::new(x, y, z).apply(transform);
+    let t = transform;
+    // the images of the x, y and z axes
+    let columns = [
+        Vector3::new(t.xx, t.xy, t.xz),
+        Vector3::new(t.yx, t.yy, t.yz),
+        Vector3::new(t.zx, t.zy, t.zz),
+    ];
+    // the largest stretch is the square root of the largest eigenvalue of A^T A,
+    // bounded by its largest absolute row sum, exact for rotations and uniform scales
+    let max_eigenvalue = columns
+        .iter()
+        .map(|column| columns.iter().map(|other| column.dot(other).abs()).sum::<f32>())
+        .fold(0.0, f32::max);
+    [center.x, center.y, center.z, radius * max_eigenvalue.sqrt()]
+}
+
+// encloses the mesh sphere of every instance, so all instances of a draw are culled together
+pub fn instances_sphere(mesh_sphere: [f32; 4], instances: &[Instance]) -> [f32; 4] {
+    let spheres: Vec<_> = instances
+        .iter()
+        .map(|instance| transform_sphere(&bytemuck::cast(instance.transform), mesh_sphere))
+        .collect();
+
+    // centered on the bounding box of the centers, not minimal but close for grids
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for sphere in &spheres {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(sphere[axis]);
+            max[axis] = max[axis].max(sphere[axis]);
+        }
+    }
+    let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+    let radius = spheres
+        .iter()
+        .map(|[x, y, z, radius]| {
+            let (dx, dy, dz) = (x - center[0], y - center[1], z - center[2]);
+            (dx * dx + dy * dy + dz * dz).sqrt() + radius
+        })
+        .fold(0.0, f32::max);
+    [center[0], center[1], center[2], radius]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    #[test]
+    fn spheres_outside_the_frustum_are_culled() {
+        // at the origin looking along +z, 2 wide and high at distance 1
+        let mut camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), 2.0, 2.0, 0.1, 1.0, 1.0);
+        camera.update();
+        let planes = camera.to_render().frustum_planes();
+
+        assert!(sphere_visible(&planes, [0.0, 0.0, 5.0, 0.5]));
+        // behind the camera, the near plane passes through its position
+        assert!(!sphere_visible(&planes, [0.0, 0.0, -2.0, 0.5]));
+        assert!(!sphere_visible(&planes, [0.0, 0.0, -0.05, 0.01]));
+        // off to the side, and overlapping the side plane
+        assert!(!sphere_visible(&planes, [10.0, 0.0, 5.0, 0.5]));
+        assert!(sphere_visible(&planes, [5.5, 0.0, 5.0, 0.5]));
+        assert!(!sphere_visible(&planes, [0.0, -10.0, 5.0, 0.5]));
+    }
+
+    #[test]
+    fn instance_spheres_are_enclosed() {
+        let instances: Vec<_> = [-2.0, 0.0, 4.0]
+            .iter()
+            .map(|&x| Instance::new(&Affine3::IDENTITY.translate(&Vector3::new(x, 0.0, 0.0)), [255; 4], 0))
+            .collect();
+        let sphere = instances_sphere([0.0, 1.0, 0.0, 1.0], &instances);
+        assert_eq!(sphere, [1.0, 1.0, 0.0, 4.0]);
+
+        let scaled = Affine3::IDENTITY.scale(&crate::math::Scale3::new(2.0, 3.0, 1.0));
+        assert_eq!(transform_sphere(&scaled, [1.0, 1.0, 1.0, 1.0]), [2.0, 3.0, 1.0, 3.0]);
+    }
+}
@@ -1,81 +1,313 @@
-use std::{ffi::c_char, mem};
+use std::ffi::{c_char, CStr};
 
 use ash::{extensions::khr::{Surface, Swapchain}, vk};
 
+use super::RendererError;
+
 const DEVICE_EXTENSION_NAMES: &[*const c_char] = &[
     Swapchain::name().as_ptr(),
 ];
 
+pub struct QueueFamilyIndices {
+    pub graphics: u32,
+    // headless rendering has no surface to present to
+    pub present: Option<u32>,
+    // a family without graphics, uploads go through the graphics family when there is none
+    pub transfer: Option<u32>,
+    // a family without graphics, for async compute, it may be the transfer family
+    pub compute: Option<u32>,
+}
+
+// picks a physical device by name or index instead of by score,
+// e.g. ASH_LEARN_DEVICE=llvmpipe to force lavapipe
+#[derive(Clone, Debug)]
+pub enum DevicePreference {
+    Index(usize),
+    Name(String),
+}
+
+impl DevicePreference {
+    pub fn parse(value: &str) -> Self {
+        match value.parse() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(value.to_owned()),
+        }
+    }
+
+    fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            Self::Index(preferred) => *preferred == index,
+            Self::Name(preferred) => name.to_lowercase().contains(&preferred.to_lowercase()),
+        }
+    }
+}
+
+impl std::fmt::Display for DevicePreference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "index {}", index),
+            Self::Name(name) => write!(f, "name {:?}", name),
+        }
+    }
+}
+
+fn device_type_score(device_type: vk::PhysicalDeviceType) -> u32 {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 1,
+        _ => 0,
+    }
+}
+
 pub fn get_physical_device_and_queue_family_indices(
     instance: &ash::Instance,
-    surface: &Surface,
-    surface_khr: vk::SurfaceKHR,
-) -> (vk::PhysicalDevice, [u32; QUEUE_FAMILY_INDICES]) {
-    let physical_device = unsafe { instance.enumerate_physical_devices() }.unwrap()[0];
+    surface: Option<(&Surface, vk::SurfaceKHR)>,
+    preference: Option<&DevicePreference>,
+) -> Result<(vk::PhysicalDevice, QueueFamilyIndices), RendererError> {
+    let mut best: Option<(u32, vk::PhysicalDevice, QueueFamilyIndices)> = None;
+    // to tell which devices there are when none matches the preference
+    let mut devices = Vec::new();
+
+    for (index, physical_device) in unsafe { instance.enumerate_physical_devices() }?.into_iter().enumerate() {
+        let props = unsafe { instance.get_physical_device_properties(physical_device) };
+        let name = unsafe { CStr::from_ptr(props.device_name.as_ptr()) }.to_string_lossy();
+
+        let queue_family_indices = check_physical_device(instance, physical_device, surface)?;
+        devices.push(match queue_family_indices {
+            Some(_) => format!("{index}: {name}"),
+            None => format!("{index}: {name} (unsuitable)"),
+        });
+
+        if let Some(preference) = preference {
+            if !preference.matches(index, &name) {
+                continue;
+            }
+        }
+
+        let Some(queue_family_indices) = queue_family_indices else {
+            log::info!("Skipping unsuitable physical device {index}: {name}");
+            continue;
+        };
+
+        let score = device_type_score(props.device_type);
+        if best.as_ref().map_or(true, |(best_score, ..)| score > *best_score) {
+            best = Some((score, physical_device, queue_family_indices));
+        }
+    }
+
+    let (_, physical_device, queue_family_indices) = best.ok_or_else(|| match preference {
+        Some(preference) => RendererError::NoPreferredDevice {
+            preference: preference.clone(),
+            devices,
+        },
+        None => RendererError::NoSuitableDevice,
+    })?;
+
+    let props = unsafe { instance.get_physical_device_properties(physical_device) };
+    log::info!("Using physical device {}", unsafe { CStr::from_ptr(props.device_name.as_ptr()) }.to_string_lossy());
+
+    Ok((physical_device, queue_family_indices))
+}
+
+// the queue families of the device if it has everything we render with, None otherwise
+fn check_physical_device(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    surface: Option<(&Surface, vk::SurfaceKHR)>,
+) -> Result<Option<QueueFamilyIndices>, RendererError> {
+    let features = unsafe { instance.get_physical_device_features(physical_device) };
+    if features.sampler_anisotropy == vk::FALSE {
+        return Ok(None);
+    }
+
+    if let Some((surface, surface_khr)) = surface {
+        if missing_extension(instance, physical_device, DEVICE_EXTENSION_NAMES)?.is_some() {
+            return Ok(None);
+        }
+
+        let formats = unsafe { surface.get_physical_device_surface_formats(physical_device, surface_khr) }?;
+        let present_modes = unsafe { surface.get_physical_device_surface_present_modes(physical_device, surface_khr) }?;
+        if formats.is_empty() || present_modes.is_empty() {
+            return Ok(None);
+        }
+    }
 
     let queue_family_props = unsafe {instance.get_physical_device_queue_family_properties(physical_device)};
 
     const INVALID_INDEX: u32 = u32::MAX;
     let mut graphics = INVALID_INDEX;
     let mut present = INVALID_INDEX;
+    let mut transfer = INVALID_INDEX;
+    let mut compute = INVALID_INDEX;
 
-    for (index, family_props) in queue_family_props.iter().filter(|p| p.queue_count > 0).enumerate() {
+    // enumerate before filtering, the index has to be the family's index on the device
+    for (index, family_props) in queue_family_props.iter().enumerate().filter(|(_, p)| p.queue_count > 0) {
         let index = index as u32;
+        let flags = family_props.queue_flags;
 
-        if family_props.queue_flags.contains(vk::QueueFlags::GRAPHICS) && graphics == INVALID_INDEX {
+        if flags.contains(vk::QueueFlags::GRAPHICS) && graphics == INVALID_INDEX {
             graphics = index;
         }
 
+        // prefer a transfer only family (usually a DMA engine) over one which can also compute
+        if flags.contains(vk::QueueFlags::TRANSFER) && !flags.contains(vk::QueueFlags::GRAPHICS) {
+            let transfer_only = !flags.contains(vk::QueueFlags::COMPUTE);
+            if transfer == INVALID_INDEX
+                || (transfer_only && queue_family_props[transfer as usize].queue_flags.contains(vk::QueueFlags::COMPUTE))
+            {
+                transfer = index;
+            }
+        }
+
+        if flags.contains(vk::QueueFlags::COMPUTE) && !flags.contains(vk::QueueFlags::GRAPHICS) && compute == INVALID_INDEX {
+            compute = index;
+        }
+
+        let Some((surface, surface_khr)) = surface else {
+            continue;
+        };
         let present_support = unsafe {
             surface.get_physical_device_surface_support(physical_device, index, surface_khr)
-        }.unwrap();
-        if present_support && (present == INVALID_INDEX || (graphics != INVALID_INDEX && graphics == present))
-        {
+        }?;
+        // the graphics family is preferred, presenting from it needs no ownership transfer
+        if present_support && (present == INVALID_INDEX || index == graphics) {
             present = index;
         }
     }
 
-    (
-        physical_device,
-        [graphics, present],
-    )
+    if graphics == INVALID_INDEX || (surface.is_some() && present == INVALID_INDEX) {
+        return Ok(None);
+    }
+
+    Ok(Some(QueueFamilyIndices {
+        graphics,
+        present: surface.map(|_| present),
+        transfer: (transfer != INVALID_INDEX).then_some(transfer),
+        compute: (compute != INVALID_INDEX).then_some(compute),
+    }))
 }
 
-pub const QUEUE_FAMILY_INDICES: usize = 2; 
-pub fn create_logical_device_and_queues(
+fn missing_extension(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
-    queue_family_indices: &[u32; QUEUE_FAMILY_INDICES],
-) -> (ash::Device, [vk::Queue; QUEUE_FAMILY_INDICES]) {
+    extension_names: &[*const c_char],
+) -> Result<Option<String>, RendererError> {
+    let available_extensions = unsafe { instance.enumerate_device_extension_properties(physical_device) }?;
+    for &name in extension_names {
+        let name = unsafe { CStr::from_ptr(name) };
+        if !available_extensions.iter().any(|props| unsafe { CStr::from_ptr(props.extension_name.as_ptr()) } == name) {
+            return Ok(Some(name.to_string_lossy().into_owned()));
+        }
+    }
+    Ok(None)
+}
 
-    let mut queue_infos = [unsafe { mem::zeroed() }; QUEUE_FAMILY_INDICES];
-    for i in 0..QUEUE_FAMILY_INDICES {
-        queue_infos[i] = vk::DeviceQueueCreateInfo::builder()
-            .queue_family_index(queue_family_indices[i])
-            .queue_priorities(&[1.0])
-            .build()
+// the optional features that were enabled
+pub struct Features {
+    pub core: vk::PhysicalDeviceFeatures,
+    // Vulkan 1.2, lets the gpu decide how many indirect draws to issue
+    pub draw_indirect_count: bool,
+}
+
+pub struct Queues {
+    pub graphics: vk::Queue,
+    pub present: Option<vk::Queue>,
+    pub transfer: Option<vk::Queue>,
+    pub compute: Option<vk::Queue>,
+}
+
+pub fn create_logical_device_and_queues(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    queue_family_indices: &QueueFamilyIndices,
+) -> Result<(ash::Device, Queues, Features), RendererError> {
+    // a queue family may only appear once in the create infos
+    let mut unique_family_indices = vec![queue_family_indices.graphics];
+    for family_index in [
+        queue_family_indices.present,
+        queue_family_indices.transfer,
+        queue_family_indices.compute,
+    ].into_iter().flatten() {
+        if !unique_family_indices.contains(&family_index) {
+            unique_family_indices.push(family_index);
+        }
     }
 
+    let queue_infos: Vec<_> = unique_family_indices
+        .iter()
+        .map(|&family_index| {
+            vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(family_index)
+                .queue_priorities(&[1.0])
+                .build()
+        })
+        .collect();
+
+    let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
     let enabled_featues = vk::PhysicalDeviceFeatures::builder()
         .sampler_anisotropy(true)
+        // optional, used by wireframe and debug line pipelines when available
+        .fill_mode_non_solid(supported_features.fill_mode_non_solid == vk::TRUE)
+        .wide_lines(supported_features.wide_lines == vk::TRUE)
+        // optional, indirect draws are issued one at a time or from the cpu without them
+        .multi_draw_indirect(supported_features.multi_draw_indirect == vk::TRUE)
+        .draw_indirect_first_instance(supported_features.draw_indirect_first_instance == vk::TRUE)
         .build();
 
-    let info = vk::DeviceCreateInfo::builder()
+    // the 1.2 feature struct may only be queried from devices supporting 1.2
+    let api_version = unsafe { instance.get_physical_device_properties(physical_device) }.api_version;
+    let mut supported_features_12 = vk::PhysicalDeviceVulkan12Features::default();
+    if api_version >= vk::API_VERSION_1_2 {
+        unsafe {
+            instance.get_physical_device_features2(
+                physical_device,
+                &mut vk::PhysicalDeviceFeatures2::builder().push_next(&mut supported_features_12),
+            );
+        }
+    }
+    let mut enabled_features_12 = vk::PhysicalDeviceVulkan12Features::builder()
+        .draw_indirect_count(supported_features_12.draw_indirect_count == vk::TRUE);
+
+    let extension_names: &[*const c_char] = match queue_family_indices.present {
+        Some(_) => DEVICE_EXTENSION_NAMES,
+        None => &[],
+    };
+
+    if let Some(name) = missing_extension(instance, physical_device, extension_names)? {
+        return Err(RendererError::MissingExtension(name));
+    }
+
+    let mut info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_infos)
         .enabled_features(&enabled_featues)
-        .enabled_extension_names(DEVICE_EXTENSION_NAMES);
+        .enabled_extension_names(extension_names);
+    if api_version >= vk::API_VERSION_1_2 {
+        info = info.push_next(&mut enabled_features_12);
+    }
 
     unsafe {
         let device = instance
-            .create_device(physical_device, &info, None)
-            .unwrap();
-        let mut queues = [mem::zeroed(); QUEUE_FAMILY_INDICES];
+            .create_device(physical_device, &info, None)?;
 
-        for i in 0..QUEUE_FAMILY_INDICES {
-            queues[i] = device.get_device_queue(queue_family_indices[i], 0);
-        }
+        let graphics = device.get_device_queue(queue_family_indices.graphics, 0);
+        let present = queue_family_indices.present
+            .map(|present| device.get_device_queue(present, 0));
+        let transfer = queue_family_indices.transfer
+            .map(|transfer| device.get_device_queue(transfer, 0));
+        let compute = queue_family_indices.compute
+            .map(|compute| device.get_device_queue(compute, 0));
 
-        (device, queues)
+        Ok((device, Queues {
+            graphics,
+            present,
+            transfer,
+            compute,
+        }, Features {
+            core: enabled_featues,
+            draw_indirect_count: enabled_features_12.draw_indirect_count == vk::TRUE,
+        }))
     }
 }
 
@@ -84,16 +316,16 @@ pub fn find_memory_type_index(
     supported_types: u32,
     required_properties: vk::MemoryPropertyFlags,
     available_properties: &vk::PhysicalDeviceMemoryProperties,
-) -> u32 {
+) -> Result<u32, RendererError> {
     for i in 0..available_properties.memory_type_count {
         if supported_types & (1 << i) != 0
             && available_properties.memory_types[i as usize]
                 .property_flags
                 .contains(required_properties) {
-            return i;
+            return Ok(i);
         }
     }
-    panic!("Could not find suitable memory type");
+    Err(RendererError::NoSuitableMemoryType)
 }
 
 pub fn find_depth_format(
@@ -102,14 +334,14 @@ pub fn find_depth_format(
     formats: &[vk::Format],
     tiling: vk::ImageTiling,
     features: vk::FormatFeatureFlags,
-) -> vk::Format {
+) -> Result<vk::Format, RendererError> {
     unsafe { 
         match tiling {
             vk::ImageTiling::LINEAR => {
                 for &f in formats.iter() {
                     let props = instance.get_physical_device_format_properties(physical_device, f);
                     if features & props.linear_tiling_features == features {
-                        return f;
+                        return Ok(f);
                     }
                 }
             }
@@ -117,12 +349,12 @@ pub fn find_depth_format(
                 for &f in formats.iter() {
                     let props = instance.get_physical_device_format_properties(physical_device, f);
                     if features & props.optimal_tiling_features == features {
-                        return f;
+                        return Ok(f);
                     }
                 }
             }
             _ => {},
         } 
-        panic!()
+        Err(RendererError::NoDepthFormat)
     }
 }
\ No newline at end of file
@@ -0,0 +1,126 @@
+use std::{fmt, path::PathBuf};
+
+use ash::vk;
+
+use super::{device::DevicePreference, shaders::Defines};
+use crate::assets::AssetError;
+
+#[derive(Debug)]
+pub enum RendererError {
+    DeviceLost,
+    // host or device memory, the vk::Result tells which
+    OutOfMemory(vk::Result),
+    NoSuitableDevice,
+    // no suitable device matches Config::device
+    NoPreferredDevice {
+        preference: DevicePreference,
+        // index and name of every device, marked when unsuitable
+        devices: Vec<String>,
+    },
+    NoSuitableMemoryType,
+    NoDepthFormat,
+    UnsupportedWindow,
+    UnsupportedFormat(vk::Format),
+    MissingExtension(String),
+    MissingLayer(String),
+    ShaderCompile {
+        path: String,
+        diagnostics: String,
+    },
+    // the shaders do not match the vertex layout or set layouts of their pipeline, or are not valid SPIR-V
+    ShaderInterface {
+        // file names of the shaders involved
+        shaders: String,
+        message: String,
+    },
+    // the define set was not declared with a `// permutation:` line in the shader, see ShaderCompiler::spirv
+    MissingShaderPermutation {
+        file_name: &'static str,
+        defines: Defines,
+    },
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Asset(AssetError),
+    Image(image::ImageError),
+    // e.g. texture layers of different sizes
+    InvalidTexture(String),
+    // Vulkan::read_pixels on a window, use capture_screenshot instead
+    NotOffscreen,
+    Vulkan(vk::Result),
+}
+
+impl From<vk::Result> for RendererError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_DEVICE_LOST => Self::DeviceLost,
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY
+            | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => Self::OutOfMemory(result),
+            _ => Self::Vulkan(result),
+        }
+    }
+}
+
+impl From<AssetError> for RendererError {
+    fn from(err: AssetError) -> Self {
+        Self::Asset(err)
+    }
+}
+
+impl From<image::ImageError> for RendererError {
+    fn from(err: image::ImageError) -> Self {
+        Self::Image(err)
+    }
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DeviceLost => write!(f, "device lost"),
+            Self::OutOfMemory(result) => write!(f, "out of memory ({})", result),
+            Self::NoSuitableDevice => write!(f, "no suitable physical device"),
+            Self::NoPreferredDevice { preference, devices } => write!(
+                f,
+                "no suitable physical device with {}, available devices: {}",
+                preference,
+                devices.join(", "),
+            ),
+            Self::NoSuitableMemoryType => write!(f, "no suitable memory type"),
+            Self::NoDepthFormat => write!(f, "no supported depth format"),
+            Self::UnsupportedWindow => write!(f, "unsupported window or display handle"),
+            Self::UnsupportedFormat(format) => write!(f, "unsupported format {:?}", format),
+            Self::MissingExtension(name) => write!(f, "missing extension {}", name),
+            Self::MissingLayer(name) => write!(f, "missing layer {}", name),
+            Self::ShaderCompile { path, diagnostics } => write!(f, "could not compile {}:\n{}", path, diagnostics),
+            Self::ShaderInterface { shaders, message } => write!(f, "{}: {}", shaders, message),
+            Self::MissingShaderPermutation { file_name, defines } => {
+                write!(f, "{} was not compiled with the defines", file_name)?;
+                for (name, value) in defines {
+                    match value {
+                        Some(value) => write!(f, " {}={}", name, value)?,
+                        None => write!(f, " {}", name)?,
+                    }
+                }
+                Ok(())
+            }
+            Self::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Self::Asset(err) => write!(f, "{}", err),
+            Self::Image(err) => write!(f, "{}", err),
+            Self::InvalidTexture(message) => write!(f, "invalid texture: {}", message),
+            Self::NotOffscreen => write!(f, "only offscreen render targets can be read back"),
+            Self::Vulkan(result) => write!(f, "{}", result),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Asset(err) => Some(err),
+            Self::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// golden image regression tests, rendered headless on lavapipe:
+//     cargo test golden
+// set UPDATE_GOLDEN=1 to (re)write the reference images after an intended change
+use std::path::PathBuf;
+
+use ash::vk;
+use image::RgbaImage;
+
+use crate::{camera::Camera, math::{Affine3, Vector3}, mesh::MeshData};
+use super::{device::DevicePreference, scene::Object, texture::TextureId, Config, Vulkan};
+
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 256,
+    height: 192,
+};
+
+// software rasterizers differ slightly in filtering and edge coverage
+const CHANNEL_TOLERANCE: u8 = 8;
+const MAX_MISMATCHED_PIXELS: usize = 16;
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+// independent of the ASH_LEARN_* variables, and without writing a pipeline cache
+fn fixed_config(cpu_draws: bool) -> Config {
+    Config {
+        device: Some(DevicePreference::parse("llvmpipe")),
+        pipeline_cache_path: None,
+        asset_root: None,
+        cpu_draws,
+        async_compute: false,
+    }
+}
+
+fn fixed_camera() -> Camera {
+    let mut camera = Camera::new(
+        Vector3::new(0.0, 0.0, -1.0),
+        3.0,
+        3.0 * EXTENT.height as f32 / EXTENT.width as f32,
+        0.1,
+        2.0,
+        2.0,
+    );
+    camera.update();
+    camera
+}
+
+fn render(camera: &Camera, config: &Config) -> RgbaImage {
+    let mut vulkan = Vulkan::new_headless(EXTENT, config).unwrap();
+    let mesh = vulkan.upload_mesh(&MeshData::tetrahedron());
+    vulkan.scene.add(Object {
+        mesh,
+        texture: TextureId::DEFAULT,
+        transform: Affine3::IDENTITY,
+    });
+    vulkan.update_camera(camera);
+    vulkan.draw_frame().unwrap();
+
+    RgbaImage::from_raw(EXTENT.width, EXTENT.height, vulkan.read_pixels().unwrap()).unwrap()
+}
+
+// returns the number of pixels with a channel differing by more than `tolerance`,
+// and an image marking them red over a darkened copy of the expected image
+fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> (usize, RgbaImage) {
+    let mut mismatched = 0;
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+
+    for (x, y, expected_pixel) in expected.enumerate_pixels() {
+        let actual_pixel = actual.get_pixel(x, y);
+        let exceeds = actual_pixel.0
+            .iter()
+            .zip(expected_pixel.0.iter())
+            .any(|(&a, &e)| a.abs_diff(e) > tolerance);
+
+        diff.put_pixel(x, y, if exceeds {
+            mismatched += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected_pixel.0;
+            image::Rgba([r / 4, g / 4, b / 4, 255])
+        });
+    }
+
+    (mismatched, diff)
+}
+
+fn assert_matches_golden(name: &str, actual: &RgbaImage) {
+    let golden_path = golden_dir().join(format!("{name}.png"));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        actual.save(&golden_path).unwrap();
+        return;
+    }
+
+    let expected = match image::open(&golden_path) {
+        Ok(expected) => expected.to_rgba8(),
+        Err(err) => panic!(
+            "could not open reference image {}: {err}, run with UPDATE_GOLDEN=1 to create it",
+            golden_path.display(),
+        ),
+    };
+    assert_eq!(
+        (actual.width(), actual.height()),
+        (expected.width(), expected.height()),
+        "{name}: rendered size differs from the reference image",
+    );
+
+    let (mismatched, diff) = compare(actual, &expected, CHANNEL_TOLERANCE);
+    if mismatched > MAX_MISMATCHED_PIXELS {
+        std::fs::create_dir_all(output_dir()).unwrap();
+        let actual_path = output_dir().join(format!("{name}.actual.png"));
+        let diff_path = output_dir().join(format!("{name}.diff.png"));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+
+        panic!(
+            "{name}: {mismatched} pixels differ from {} by more than {CHANNEL_TOLERANCE}, see {} and {}",
+            golden_path.display(),
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+#[test]
+fn tetrahedron() {
+    let actual = render(&fixed_camera(), &fixed_config(false));
+    assert_matches_golden("tetrahedron", &actual);
+}
+
+// the cpu fallback has to match the indirect draws
+#[test]
+fn tetrahedron_cpu_draws() {
+    let actual = render(&fixed_camera(), &fixed_config(true));
+    assert_matches_golden("tetrahedron", &actual);
+}
+
+#[test]
+fn compare_counts_pixels_outside_tolerance() {
+    let expected = RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(0, 0, image::Rgba([100 + CHANNEL_TOLERANCE, 100, 100, 255]));
+    actual.put_pixel(1, 0, image::Rgba([100, 100 - CHANNEL_TOLERANCE - 1, 100, 255]));
+
+    let (mismatched, diff) = compare(&actual, &expected, CHANNEL_TOLERANCE);
+    assert_eq!(mismatched, 1);
+    assert_eq!(diff.get_pixel(1, 0).0, [255, 0, 0, 255]);
+    assert_eq!(diff.get_pixel(0, 0).0, [25, 25, 25, 255]);
+}
@@ -1,15 +1,20 @@
 use ash::vk;
 
+use super::{allocator::{Allocator, ResourceKind}, resource::{DeletionQueue, Image, ImageView}, RendererError};
+
 pub fn create_image(
     device: &ash::Device,
-    physical_device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    allocator: &mut Allocator,
+    deletion_queue: &DeletionQueue,
     width: u32,
     height: u32,
+    array_layers: u32,
+    mip_levels: u32,
     usage: vk::ImageUsageFlags,
     format: vk::Format,
     tiling: vk::ImageTiling,
     memory_properties: vk::MemoryPropertyFlags,
-) -> (vk::Image, vk::DeviceMemory) {
+) -> Result<Image, RendererError> {
     let info = vk::ImageCreateInfo::builder()
         .image_type(vk::ImageType::TYPE_2D)
         .extent(vk::Extent3D {
@@ -17,8 +22,8 @@ pub fn create_image(
             height,
             depth: 1,
         })
-        .mip_levels(1)
-        .array_layers(1)
+        .mip_levels(mip_levels)
+        .array_layers(array_layers)
         .format(format)
         .tiling(tiling)
         .initial_layout(vk::ImageLayout::UNDEFINED)
@@ -27,34 +32,36 @@ pub fn create_image(
         .samples(vk::SampleCountFlags::TYPE_1)
         .flags(vk::ImageCreateFlags::empty());
 
-    let image = unsafe { device.create_image(&info, None).unwrap() };
+    let image = unsafe { device.create_image(&info, None)? };
 
     let mem_requirements = unsafe { device.get_image_memory_requirements(image) };
-    let mem_type_index = super::device::find_memory_type_index(
-        mem_requirements.memory_type_bits,
+    let allocation = match allocator.allocate(
+        device,
+        mem_requirements,
         memory_properties,
-        &physical_device_memory_properties,
-    );
-
-    let alloc_info = vk::MemoryAllocateInfo::builder()
-        .allocation_size(mem_requirements.size)
-        .memory_type_index(mem_type_index)
-        .build();
-    let memory = unsafe {
-        let mem = device.allocate_memory(&alloc_info, None).unwrap();
-        device.bind_image_memory(image, mem, 0).unwrap();
-        mem
+        ResourceKind::from_tiling(tiling),
+    ) {
+        Ok(allocation) => allocation,
+        Err(err) => {
+            unsafe { device.destroy_image(image, None) };
+            return Err(err);
+        }
     };
+    let bound = unsafe { device.bind_image_memory(image, allocation.memory(), allocation.offset()) };
+    // from here on dropping the image releases both
+    let image = Image::new(image, allocation, deletion_queue);
+    bound?;
 
-    (image, memory)
+    Ok(image)
 }
 
 pub fn create_image_view(
     device: &ash::Device,
+    deletion_queue: &DeletionQueue,
     image: vk::Image,
     format: vk::Format,
     aspect_mask: vk::ImageAspectFlags,
-) -> vk::ImageView {
+) -> Result<ImageView, RendererError> {
     let create_info = vk::ImageViewCreateInfo::builder()
         .image(image)
         .view_type(vk::ImageViewType::TYPE_2D)
@@ -67,5 +74,6 @@ pub fn create_image_view(
             layer_count: 1,
         });
     
-    unsafe { device.create_image_view(&create_info, None).unwrap() }
+    let image_view = unsafe { device.create_image_view(&create_info, None)? };
+    Ok(ImageView::new(image_view, deletion_queue))
 }
\ No newline at end of file
@@ -0,0 +1,121 @@
+use std::ops::Range;
+
+use crate::instance::Instance;
+
+use super::{culling, instancing::InstancedDraws, mesh::Geometry, scene::Scene, texture::TextureId};
+
+// vk::DrawIndexedIndirectCommand, which does not implement Pod
+#[derive(Clone, Copy, Debug, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct DrawCommand {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
+// the draws of a frame as written to the instance and indirect buffers,
+// drawn with the instanced pipeline, scene objects being instances of their own
+#[derive(Default)]
+pub struct IndirectDraws {
+    pub instances: Vec<Instance>,
+    // sorted by texture
+    pub commands: Vec<DrawCommand>,
+    // world space bounding sphere of each command, for culling
+    pub spheres: Vec<[f32; 4]>,
+    // the commands of each texture, one descriptor set bind and one multi draw each
+    pub batches: Vec<(TextureId, Range<u32>)>,
+}
+
+impl IndirectDraws {
+    // the instances of `instanced` keep their indices, the objects' ones follow them
+    pub fn build(scene: &Scene, instanced: &InstancedDraws, geometry: &Geometry) -> Self {
+        let mut instances = instanced.instances.clone();
+        let mut commands: Vec<(TextureId, DrawCommand, [f32; 4])> = Vec::new();
+
+        for object in scene.objects() {
+            let range = geometry.range(object.mesh);
+            let sphere = culling::transform_sphere(&object.transform, geometry.bounding_sphere(object.mesh));
+            commands.push((object.texture, DrawCommand {
+                index_count: range.index_count,
+                instance_count: 1,
+                first_index: range.first_index,
+                vertex_offset: range.vertex_offset,
+                first_instance: instances.len() as u32,
+            }, sphere));
+            // white leaves the vertex color as is, as in the non instanced pipeline
+            instances.push(Instance::new(&object.transform, [255; 4], 0));
+        }
+
+        for draw in &instanced.draws {
+            let range = geometry.range(draw.mesh);
+            let sphere = culling::instances_sphere(geometry.bounding_sphere(draw.mesh), instanced.instances(draw));
+            commands.push((draw.texture, DrawCommand {
+                index_count: range.index_count,
+                instance_count: draw.instance_count,
+                first_index: range.first_index,
+                vertex_offset: range.vertex_offset,
+                first_instance: draw.first_instance,
+            }, sphere));
+        }
+
+        // stable, so draws of the same texture stay in submission order
+        commands.sort_by_key(|(texture, ..)| texture.0);
+
+        let mut batches: Vec<(TextureId, Range<u32>)> = Vec::new();
+        for (index, &(texture, ..)) in commands.iter().enumerate() {
+            let index = index as u32;
+            match batches.last_mut() {
+                Some((batch_texture, range)) if *batch_texture == texture => range.end = index + 1,
+                _ => batches.push((texture, index..index + 1)),
+            }
+        }
+
+        Self {
+            instances,
+            spheres: commands.iter().map(|&(.., sphere)| sphere).collect(),
+            commands: commands.into_iter().map(|(_, command, _)| command).collect(),
+            batches,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use super::*;
+    use crate::{math::Affine3, mesh::MeshData, vulkan::scene::Object};
+
+    #[test]
+    fn commands_are_batched_by_texture() {
+        assert_eq!(std::mem::size_of::<DrawCommand>(), std::mem::size_of::<vk::DrawIndexedIndirectCommand>());
+
+        let mut geometry = Geometry::default();
+        let first = geometry.add(&MeshData::tetrahedron());
+        let second = geometry.add(&MeshData::tetrahedron());
+
+        let mut scene = Scene::default();
+        for (mesh, texture) in [(first, 1), (second, 0), (first, 1)] {
+            scene.add(Object {
+                mesh,
+                texture: TextureId(texture),
+                transform: Affine3::IDENTITY,
+            });
+        }
+        let mut instanced = InstancedDraws::default();
+        let instance = Instance::new(&Affine3::IDENTITY, [255; 4], 0);
+        instanced.push(second, TextureId(1), &[instance; 3]);
+
+        let draws = IndirectDraws::build(&scene, &instanced, &geometry);
+
+        assert_eq!(draws.instances.len(), 6);
+        assert_eq!(draws.batches, [(TextureId(0), 0..1), (TextureId(1), 1..4)]);
+        let firsts: Vec<_> = draws.commands
+            .iter()
+            .map(|command| (command.first_index, command.vertex_offset, command.first_instance, command.instance_count))
+            .collect();
+        assert_eq!(firsts, [(9, 4, 4, 1), (0, 0, 3, 1), (0, 0, 5, 1), (9, 4, 0, 3)]);
+    }
+}
@@ -3,15 +3,48 @@ use std::{ffi::{c_char, c_void, CStr, CString}, mem};
 use ash::{extensions::{ext::DebugUtils, khr::{Surface, Swapchain, Win32Surface}}, vk::{self, DebugUtilsMessengerEXT, SurfaceKHR, HINSTANCE, HWND}};
 use ash::extensions::*;
 use winit::raw_window_handle::{DisplayHandle, HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle, WindowHandle};
+
+use super::RendererError;
 const EXTENSION_NAMES: &[*const c_char] = &[
-    khr::Surface::name().as_ptr(), 
-    khr::Win32Surface::name().as_ptr(),
     DebugUtils::name().as_ptr()
 ];
 
+const WIN32_SURFACE_EXTENSION_NAMES: &[*const c_char] = &[
+    khr::Surface::name().as_ptr(),
+    khr::Win32Surface::name().as_ptr(),
+];
+
+const XLIB_SURFACE_EXTENSION_NAMES: &[*const c_char] = &[
+    khr::Surface::name().as_ptr(),
+    khr::XlibSurface::name().as_ptr(),
+];
+
+const XCB_SURFACE_EXTENSION_NAMES: &[*const c_char] = &[
+    khr::Surface::name().as_ptr(),
+    khr::XcbSurface::name().as_ptr(),
+];
+
+const WAYLAND_SURFACE_EXTENSION_NAMES: &[*const c_char] = &[
+    khr::Surface::name().as_ptr(),
+    khr::WaylandSurface::name().as_ptr(),
+];
+
 const LAYER_NAMES: &[*const c_char] = &["VK_LAYER_KHRONOS_validation\0".as_ptr() as *const c_char];
 
-pub fn create_instance(entry: &ash::Entry) -> ash::Instance {
+// the surface extensions depend on the windowing system we are running under,
+// so they can only be known once we have the window's display handle
+pub fn surface_extension_names(display_handle: RawDisplayHandle) -> Result<&'static [*const c_char], RendererError> {
+    match display_handle {
+        RawDisplayHandle::Windows(_) => Ok(WIN32_SURFACE_EXTENSION_NAMES),
+        RawDisplayHandle::Xlib(_) => Ok(XLIB_SURFACE_EXTENSION_NAMES),
+        RawDisplayHandle::Xcb(_) => Ok(XCB_SURFACE_EXTENSION_NAMES),
+        RawDisplayHandle::Wayland(_) => Ok(WAYLAND_SURFACE_EXTENSION_NAMES),
+        _ => Err(RendererError::UnsupportedWindow),
+    }
+}
+
+// without a display handle no surface extensions are enabled, used for headless rendering
+pub fn create_instance(entry: &ash::Entry, display_handle: Option<RawDisplayHandle>) -> Result<ash::Instance, RendererError> {
     let app_name = CString::new("Vulkan Application").unwrap();
     let engine_name = CString::new("No Engine").unwrap();
 
@@ -23,15 +56,42 @@ pub fn create_instance(entry: &ash::Entry) -> ash::Instance {
         .api_version(vk::make_api_version(0, 1, 3, 0));
 
 
+    let mut extension_names = EXTENSION_NAMES.to_vec();
+    if let Some(display_handle) = display_handle {
+        extension_names.extend_from_slice(surface_extension_names(display_handle)?);
+    }
+
+    let available_extensions = entry.enumerate_instance_extension_properties(None)?;
+    for &name in &extension_names {
+        let name = unsafe { CStr::from_ptr(name) };
+        if !available_extensions.iter().any(|props| unsafe { CStr::from_ptr(props.extension_name.as_ptr()) } == name) {
+            return Err(RendererError::MissingExtension(name.to_string_lossy().into_owned()));
+        }
+    }
+
+    // headless rendering also runs on CI images without the validation layers installed
+    let available_layers = entry.enumerate_instance_layer_properties()?;
+    let mut layer_names = Vec::new();
+    for &name in LAYER_NAMES {
+        let layer = unsafe { CStr::from_ptr(name) };
+        if available_layers.iter().any(|props| unsafe { CStr::from_ptr(props.layer_name.as_ptr()) } == layer) {
+            layer_names.push(name);
+        } else if display_handle.is_none() {
+            log::warn!("Layer {} is not available, running without it", layer.to_string_lossy());
+        } else {
+            return Err(RendererError::MissingLayer(layer.to_string_lossy().into_owned()));
+        }
+    }
+
     let info = vk::InstanceCreateInfo::builder()
         .application_info(&app_info)
-        .enabled_extension_names(&EXTENSION_NAMES)
-        .enabled_layer_names(&LAYER_NAMES);
+        .enabled_extension_names(&extension_names)
+        .enabled_layer_names(&layer_names);
 
-    unsafe { entry.create_instance(&info, None).unwrap() }
+    Ok(unsafe { entry.create_instance(&info, None)? })
 }
 
-pub fn create_messenger(debug_utils: &DebugUtils) -> DebugUtilsMessengerEXT {
+pub fn create_messenger(debug_utils: &DebugUtils) -> Result<DebugUtilsMessengerEXT, RendererError> {
     let create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
         .message_severity(
             vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
@@ -46,11 +106,10 @@ pub fn create_messenger(debug_utils: &DebugUtils) -> DebugUtilsMessengerEXT {
         )
         .pfn_user_callback(Some(vulkan_debug_callback));
 
-    unsafe {
+    Ok(unsafe {
         debug_utils
-            .create_debug_utils_messenger(&create_info, None)
-            .unwrap()
-    }
+            .create_debug_utils_messenger(&create_info, None)?
+    })
 }
 
 unsafe extern "system" fn vulkan_debug_callback(
@@ -76,18 +135,40 @@ unsafe extern "system" fn vulkan_debug_callback(
 }
 
 
-pub unsafe fn create_surface(entry: &ash::Entry, instance: &ash::Instance, window: &winit::window::Window) -> SurfaceKHR {
-    let display_handle = window.display_handle().unwrap().as_raw();
-    let window_handle = window.window_handle().unwrap().as_raw();
+pub unsafe fn create_surface(entry: &ash::Entry, instance: &ash::Instance, window: &winit::window::Window) -> Result<SurfaceKHR, RendererError> {
+    let display_handle = window.display_handle().map_err(|_| RendererError::UnsupportedWindow)?.as_raw();
+    let window_handle = window.window_handle().map_err(|_| RendererError::UnsupportedWindow)?.as_raw();
 
-    match (display_handle, window_handle) {
+    let surface_khr = match (display_handle, window_handle) {
         (RawDisplayHandle::Windows(_), RawWindowHandle::Win32(window)) => {
             let surface_desc: vk::Win32SurfaceCreateInfoKHRBuilder<'_> = vk::Win32SurfaceCreateInfoKHR::builder()
-                .hinstance(window.hinstance.unwrap().get() as HINSTANCE)
+                .hinstance(window.hinstance.ok_or(RendererError::UnsupportedWindow)?.get() as HINSTANCE)
                 .hwnd(window.hwnd.get() as HWND);
             let surface_fn = khr::Win32Surface::new(entry, instance);
-            surface_fn.create_win32_surface(&surface_desc, None).unwrap()
+            surface_fn.create_win32_surface(&surface_desc, None)?
         }
-        _ => unimplemented!()
-    }
+        (RawDisplayHandle::Xlib(display), RawWindowHandle::Xlib(window)) => {
+            let surface_desc = vk::XlibSurfaceCreateInfoKHR::builder()
+                .dpy(display.display.ok_or(RendererError::UnsupportedWindow)?.as_ptr() as *mut vk::Display)
+                .window(window.window as vk::Window);
+            let surface_fn = khr::XlibSurface::new(entry, instance);
+            surface_fn.create_xlib_surface(&surface_desc, None)?
+        }
+        (RawDisplayHandle::Xcb(display), RawWindowHandle::Xcb(window)) => {
+            let surface_desc = vk::XcbSurfaceCreateInfoKHR::builder()
+                .connection(display.connection.ok_or(RendererError::UnsupportedWindow)?.as_ptr())
+                .window(window.window.get());
+            let surface_fn = khr::XcbSurface::new(entry, instance);
+            surface_fn.create_xcb_surface(&surface_desc, None)?
+        }
+        (RawDisplayHandle::Wayland(display), RawWindowHandle::Wayland(window)) => {
+            let surface_desc = vk::WaylandSurfaceCreateInfoKHR::builder()
+                .display(display.display.as_ptr())
+                .surface(window.surface.as_ptr());
+            let surface_fn = khr::WaylandSurface::new(entry, instance);
+            surface_fn.create_wayland_surface(&surface_desc, None)?
+        }
+        _ => return Err(RendererError::UnsupportedWindow),
+    };
+    Ok(surface_khr)
 }
\ No newline at end of file
@@ -0,0 +1,43 @@
+use crate::instance::Instance;
+
+use super::{mesh::MeshId, texture::TextureId};
+
+pub struct InstancedDraw {
+    pub mesh: MeshId,
+    pub texture: TextureId,
+    pub first_instance: u32,
+    pub instance_count: u32,
+}
+
+// instances queued with Vulkan::draw_instanced for the next frame
+#[derive(Default)]
+pub struct InstancedDraws {
+    pub draws: Vec<InstancedDraw>,
+    pub instances: Vec<Instance>,
+}
+
+impl InstancedDraws {
+    pub fn push(&mut self, mesh: MeshId, texture: TextureId, instances: &[Instance]) {
+        if instances.is_empty() {
+            return;
+        }
+        self.draws.push(InstancedDraw {
+            mesh,
+            texture,
+            first_instance: self.instances.len() as u32,
+            instance_count: instances.len() as u32,
+        });
+        self.instances.extend_from_slice(instances);
+    }
+
+    pub fn instances(&self, draw: &InstancedDraw) -> &[Instance] {
+        let first = draw.first_instance as usize;
+        &self.instances[first..first + draw.instance_count as usize]
+    }
+
+    // after the frame is recorded, draw_instanced is called again for every frame
+    pub fn clear(&mut self) {
+        self.draws.clear();
+        self.instances.clear();
+    }
+}
@@ -0,0 +1,146 @@
+use ash::vk;
+
+use crate::mesh::MeshData;
+
+use super::{
+    allocator::Allocator,
+    buffer,
+    resource::{Buffer, DeletionQueue},
+    upload::{self, BufferUpload, UploadQueues},
+    vertex::{Index, Vertex},
+    RendererError,
+};
+
+// returned by Vulkan::upload_mesh
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshId(pub(super) usize);
+
+// where a mesh lies in the shared buffers, in the terms of cmd_draw_indexed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshRange {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+}
+
+// every mesh in one vertex and one index buffer, so they are bound once for all draws,
+// the cpu side copies are kept to rebuild both buffers once meshes were added
+#[derive(Default)]
+pub struct Geometry {
+    vertices: Vec<Vertex>,
+    indices: Vec<Index>,
+    // indexed by MeshId
+    ranges: Vec<MeshRange>,
+    // model space, see MeshData::bounding_sphere
+    bounding_spheres: Vec<[f32; 4]>,
+    // vertex and index buffer, None until the first flush with any meshes
+    buffers: Option<(Buffer, Buffer)>,
+    uploaded_mesh_count: usize,
+}
+
+impl Geometry {
+    // drawable after the next flush
+    pub fn add(&mut self, mesh: &MeshData) -> MeshId {
+        self.ranges.push(MeshRange {
+            first_index: self.indices.len() as u32,
+            index_count: mesh.indices.len() as u32,
+            vertex_offset: self.vertices.len() as i32,
+        });
+        self.bounding_spheres.push(mesh.bounding_sphere());
+        self.vertices.extend_from_slice(&mesh.vertices);
+        self.indices.extend_from_slice(&mesh.indices);
+        MeshId(self.ranges.len() - 1)
+    }
+
+    pub fn range(&self, mesh: MeshId) -> MeshRange {
+        self.ranges[mesh.0]
+    }
+
+    pub fn bounding_sphere(&self, mesh: MeshId) -> [f32; 4] {
+        self.bounding_spheres[mesh.0]
+    }
+
+    // vertex and index buffer
+    pub fn buffers(&self) -> Option<(vk::Buffer, vk::Buffer)> {
+        self.buffers.as_ref().map(|(vertices, indices)| (vertices.handle(), indices.handle()))
+    }
+
+    // replaces the buffers if meshes were added since the last flush,
+    // the old ones go to the deletion queue as frames in flight may still read them
+    pub fn flush(
+        &mut self,
+        device: &ash::Device,
+        allocator: &mut Allocator,
+        deletion_queue: &DeletionQueue,
+        queues: &UploadQueues,
+    ) -> Result<(), RendererError> {
+        if self.uploaded_mesh_count == self.ranges.len() || self.indices.is_empty() {
+            return Ok(());
+        }
+
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(&self.vertices);
+        let index_bytes: &[u8] = bytemuck::cast_slice(&self.indices);
+
+        let vertex_buffer = buffer::create_buffer(
+            device,
+            allocator,
+            deletion_queue,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vertex_bytes.len() as vk::DeviceSize,
+        )?;
+        let index_buffer = buffer::create_buffer(
+            device,
+            allocator,
+            deletion_queue,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            index_bytes.len() as vk::DeviceSize,
+        )?;
+
+        upload::upload(
+            device,
+            allocator,
+            deletion_queue,
+            queues,
+            &[
+                BufferUpload {
+                    buffer: vertex_buffer.handle(),
+                    bytes: vertex_bytes,
+                    dst_stage: vk::PipelineStageFlags::VERTEX_INPUT,
+                    dst_access: vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+                },
+                BufferUpload {
+                    buffer: index_buffer.handle(),
+                    bytes: index_bytes,
+                    dst_stage: vk::PipelineStageFlags::VERTEX_INPUT,
+                    dst_access: vk::AccessFlags::INDEX_READ,
+                },
+            ],
+            &[],
+        )?;
+
+        self.buffers = Some((vertex_buffer, index_buffer));
+        self.uploaded_mesh_count = self.ranges.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meshes_are_appended() {
+        let mut geometry = Geometry::default();
+        let tetrahedron = geometry.add(&MeshData::tetrahedron());
+        let empty = geometry.add(&MeshData::default());
+        let second = geometry.add(&MeshData::tetrahedron());
+
+        assert_eq!(geometry.range(tetrahedron), MeshRange { first_index: 0, index_count: 9, vertex_offset: 0 });
+        assert_eq!(geometry.range(empty), MeshRange { first_index: 9, index_count: 0, vertex_offset: 4 });
+        // indices stay relative to the mesh, the vertex offset is added when drawing
+        assert_eq!(geometry.range(second), MeshRange { first_index: 9, index_count: 9, vertex_offset: 4 });
+        assert_eq!(geometry.indices[9..], geometry.indices[..9]);
+    }
+}
@@ -0,0 +1,62 @@
+use ash::vk;
+
+use super::{allocator::Allocator, resource::{DeletionQueue, Framebuffer, Image, ImageView}, RendererError};
+
+// rendering target used when there is no window to present to,
+// the color attachment is left in TRANSFER_SRC_OPTIMAL so it can be read back
+pub struct OffscreenTarget {
+    pub format: vk::Format,
+    pub image: Image,
+    // only kept alive for the framebuffer
+    pub image_view: ImageView,
+    pub framebuffer: Framebuffer,
+}
+
+pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+pub fn create_offscreen_target(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    deletion_queue: &DeletionQueue,
+    extent: vk::Extent2D,
+    depth_image_view: vk::ImageView,
+    render_pass: vk::RenderPass,
+) -> Result<OffscreenTarget, RendererError> {
+    let image = super::img::create_image(
+        device,
+        allocator,
+        deletion_queue,
+        extent.width,
+        extent.height,
+        1,
+        1,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+        OFFSCREEN_FORMAT,
+        vk::ImageTiling::OPTIMAL,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+
+    let image_view = super::img::create_image_view(
+        device,
+        deletion_queue,
+        image.handle(),
+        OFFSCREEN_FORMAT,
+        vk::ImageAspectFlags::COLOR,
+    )?;
+
+    let framebuffer = super::swapchain::create_swapchain_framebuffers(
+        device,
+        deletion_queue,
+        std::slice::from_ref(&image_view),
+        depth_image_view,
+        render_pass,
+        extent,
+    )?.remove(0);
+
+    Ok(OffscreenTarget {
+        format: OFFSCREEN_FORMAT,
+        image,
+        image_view,
+        framebuffer,
+    })
+}
@@ -1,123 +1,17 @@
-use std::{ffi::CString, io::Read, mem};
+use std::ffi::CString;
 
 use ash::vk;
-#[repr(C)]
-pub struct Vertex {
-    pub position: [f32; 3],
-    pub color: [f32; 3],
-    pub tex_coord: [f32; 2],
-}
 
-pub type Index = u32;
-
-// TODO: write a proc macro which annotates the vertex struct and generate the attribute descriptor
-const VERTEX_ATTRIB_DESCS: &[vk::VertexInputAttributeDescription] = &[
-    vk::VertexInputAttributeDescription {
-        location: 0,
-        binding: 0,
-        format: vk::Format::R32G32B32_SFLOAT,
-        offset: 0,
-    },
-    vk::VertexInputAttributeDescription {
-        location: 1,
-        binding: 0,
-        format: vk::Format::R32G32B32_SFLOAT,
-        offset: core::mem::offset_of!(Vertex, color) as u32,
-    },
-    vk::VertexInputAttributeDescription {
-        location: 2,
-        binding: 0,
-        format: vk::Format::R32G32_SFLOAT,
-        offset: core::mem::offset_of!(Vertex, tex_coord) as u32,
-    },
-];
-
-const BINDING_DESCS: &[vk::VertexInputBindingDescription] = &[
-    vk::VertexInputBindingDescription {
-        binding: 0,
-        stride: mem::size_of::<Vertex>() as u32,
-        input_rate: vk::VertexInputRate::VERTEX,
-    }
-];
-
-pub fn new_pipeline_and_layout(
-    device: &ash::Device,
-    descriptor_set_layout: vk::DescriptorSetLayout,
-    shader_compiler: &shaderc::Compiler,
-    render_pass: vk::RenderPass,
-    vertex_shader_path: &str,
-    fragment_shader_path: &str,
-) -> (vk::Pipeline, vk::PipelineLayout) {
-
-    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
-        .dynamic_states(&[
-            vk::DynamicState::VIEWPORT,
-            vk::DynamicState::SCISSOR,
-        ])
-        .build();
-
-    let vert_module = create_shader_module(
-        device, 
-        &shader_compiler, 
-        vertex_shader_path,
-        shaderc::ShaderKind::Vertex,
-    );
-    let frag_module = create_shader_module(
-        device, 
-        &shader_compiler, 
-        fragment_shader_path,
-        shaderc::ShaderKind::Fragment,
-    );
-
-    let entry_name = CString::new("main").unwrap();
-    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
-        .stage(vk::ShaderStageFlags::VERTEX)
-        .module(vert_module)
-        .name(&entry_name)
-        .build();
-    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
-        .stage(vk::ShaderStageFlags::FRAGMENT)
-        .module(frag_module)
-        .name(&entry_name)
-        .build();
-
-    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
-        .vertex_attribute_descriptions(VERTEX_ATTRIB_DESCS)
-        .vertex_binding_descriptions(BINDING_DESCS)
-        .build();
-
-    let input_assembly_create: vk::PipelineInputAssemblyStateCreateInfo = vk::PipelineInputAssemblyStateCreateInfo::builder()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-        .primitive_restart_enable(false)
-        .build();
-
-    let viewport_create = vk::PipelineViewportStateCreateInfo::builder()
-        .scissor_count(1)
-        .viewport_count(1)
-        .build();
-
-    let rasterizer_create = vk::PipelineRasterizationStateCreateInfo::builder()
-        .depth_clamp_enable(false)
-        .rasterizer_discard_enable(false)
-        .polygon_mode(vk::PolygonMode::FILL)
-        .line_width(1.0)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-        .depth_bias_enable(false)
-        .depth_bias_constant_factor(0.0)
-        .depth_bias_clamp(0.0)
-        .depth_bias_slope_factor(0.0)
-        .build();
-
-    let multisampling_create = vk::PipelineMultisampleStateCreateInfo::builder()
-        .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1)
-        .min_sample_shading(1.0)
-        .alpha_to_coverage_enable(false)
-        .alpha_to_one_enable(false)
-        .build();
-
-    let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
+use super::{
+    reflect::{self, PipelineReflection},
+    resource::{DeletionQueue, DescriptorSetLayout, Pipeline, PipelineLayout},
+    shaders::{Defines, EmbeddedShader, ShaderCompiler, ShaderOptions},
+    vertex::VertexLayout,
+    RendererError,
+};
+
+pub fn opaque_blend() -> vk::PipelineColorBlendAttachmentState {
+    vk::PipelineColorBlendAttachmentState::builder()
         .color_write_mask(vk::ColorComponentFlags::RGBA)
         .blend_enable(false)
         .src_color_blend_factor(vk::BlendFactor::ONE)
@@ -126,83 +20,589 @@ pub fn new_pipeline_and_layout(
         .src_alpha_blend_factor(vk::BlendFactor::ONE)
         .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
         .alpha_blend_op(vk::BlendOp::ADD)
-        .build();
-    let color_blend_attachments = [color_blend_attachment];
-
-    let color_blending = vk::PipelineColorBlendStateCreateInfo::builder()
-        .logic_op_enable(false)
-        .logic_op(vk::LogicOp::COPY)
-        .attachments(&color_blend_attachments)
-        .blend_constants([0.0, 0.0, 0.0, 0.0])
-        .build();
-
-    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
-        .depth_test_enable(true)
-        .depth_write_enable(true)
-        .depth_compare_op(vk::CompareOp::GREATER)
-        .build();
-
-    let layout = {
-        let layout = vk::PipelineLayoutCreateInfo::builder()
-            .set_layouts(&[
-                descriptor_set_layout,
-            ])
-            .build();
+        .build()
+}
 
-        unsafe { device.create_pipeline_layout(&layout, None).unwrap() }
-    };
-
-    let info = vk::GraphicsPipelineCreateInfo::builder()
-        .dynamic_state(&dynamic_state)
-        .stages(&[vert_stage, frag_stage])
-        .input_assembly_state(&input_assembly_create)
-        .viewport_state(&viewport_create)
-        .rasterization_state(&rasterizer_create)
-        .multisample_state(&multisampling_create)
-        .vertex_input_state(&vertex_input_state)
-        .depth_stencil_state(&depth_stencil_state)
-        .color_blend_state(&color_blending)
-        .layout(layout)
-        .render_pass(render_pass)
-        .subpass(0) // what does this do?!
-        .build();
-    let pipeline = unsafe {
-        device
-            .create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)
-            .unwrap()[0]
-    };
+// non premultiplied "over" blending for transparent geometry
+pub fn alpha_blend() -> vk::PipelineColorBlendAttachmentState {
+    vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .build()
+}
+
+pub fn additive_blend() -> vk::PipelineColorBlendAttachmentState {
+    vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .build()
+}
+
+// the defaults describe the main pipeline: filled triangle lists with back face culling,
+// one opaque color attachment and a depth test against reversed z (GREATER),
+// with the viewport and scissor set while recording
+#[derive(Clone)]
+pub struct GraphicsPipelineBuilder {
+    vertex_shader: EmbeddedShader,
+    fragment_shader: EmbeddedShader,
+    // applied to both stages
+    defines: Defines,
+    shader_options: ShaderOptions,
+    vertex_layout: VertexLayout,
+
+    topology: vk::PrimitiveTopology,
+    polygon_mode: vk::PolygonMode,
+    line_width: f32,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
 
-    unsafe {
-        device.destroy_shader_module(vert_module, None);
-        device.destroy_shader_module(frag_module, None);
-    };
+    color_blend_attachments: Vec<vk::PipelineColorBlendAttachmentState>,
 
-    (pipeline, layout)
+    depth_test: bool,
+    depth_write: bool,
+    depth_compare_op: vk::CompareOp,
+
+    // with their bindings, to check them against the shaders
+    set_layouts: Vec<(vk::DescriptorSetLayout, Vec<vk::DescriptorSetLayoutBinding>)>,
+    dynamic_states: Vec<vk::DynamicState>,
 }
 
-fn create_shader_module(
-    device: &ash::Device, 
-    shader_compiler: &shaderc::Compiler, 
-    file_path: &str,
-    shader_kind: shaderc::ShaderKind,
-) -> vk::ShaderModule {
-    let mut file = std::fs::File::open(file_path).unwrap();
-    let mut source = String::new();
-    file.read_to_string(&mut source).unwrap();
-
-    let code = shader_compiler.compile_into_spirv(
-        &source, 
-        shader_kind, 
-        file_path, 
-        "main",
-        None,
-    ).unwrap().as_binary().to_vec();
+impl GraphicsPipelineBuilder {
+    pub fn new(vertex_shader: EmbeddedShader, fragment_shader: EmbeddedShader) -> Self {
+        Self {
+            vertex_shader,
+            fragment_shader,
+            defines: Defines::new(),
+            shader_options: ShaderOptions::default(),
+            vertex_layout: VertexLayout::default(),
+
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            line_width: 1.0,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+
+            color_blend_attachments: vec![opaque_blend()],
+
+            depth_test: true,
+            depth_write: true,
+            depth_compare_op: vk::CompareOp::GREATER,
+
+            set_layouts: Vec::new(),
+            dynamic_states: vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+        }
+    }
+
+    // selects the permutation of both shaders compiled with these defines,
+    // they are compiled when building unless both declare it
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.defines.insert(name.to_owned(), value.map(str::to_owned));
+        self
+    }
+
+    // instead of the cargo profile's, the shaders are then compiled when building
+    pub fn optimization(mut self, optimization: shaderc::OptimizationLevel) -> Self {
+        self.shader_options.optimization = Some(optimization);
+        self
+    }
+
+    // instead of the cargo profile's, the shaders are then compiled when building
+    pub fn debug_info(mut self, debug_info: bool) -> Self {
+        self.shader_options.debug_info = Some(debug_info);
+        self
+    }
+
+    pub fn vertex_layout(mut self, vertex_layout: VertexLayout) -> Self {
+        self.vertex_layout = vertex_layout;
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    // anything but FILL needs the fill_mode_non_solid feature
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    // widths other than 1.0 need the wide_lines feature
+    pub fn line_width(mut self, line_width: f32) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags, front_face: vk::FrontFace) -> Self {
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self
+    }
+
+    // one per color attachment of the subpass
+    pub fn color_blend_attachments(mut self, attachments: &[vk::PipelineColorBlendAttachmentState]) -> Self {
+        self.color_blend_attachments = attachments.to_vec();
+        self
+    }
+
+    pub fn depth(mut self, test: bool, write: bool, compare_op: vk::CompareOp) -> Self {
+        self.depth_test = test;
+        self.depth_write = write;
+        self.depth_compare_op = compare_op;
+        self
+    }
+
+    // in set order, usually created from the bindings in reflect()
+    pub fn set_layouts(mut self, set_layouts: &[&DescriptorSetLayout]) -> Self {
+        self.set_layouts = set_layouts
+            .iter()
+            .map(|layout| (layout.handle(), layout.bindings().to_vec()))
+            .collect();
+        self
+    }
+
+    pub fn dynamic_states(mut self, dynamic_states: &[vk::DynamicState]) -> Self {
+        self.dynamic_states = dynamic_states.to_vec();
+        self
+    }
+
+    // what the shaders declare, push constant ranges are taken from it when building
+    pub fn reflect(&self, shader_compiler: &mut ShaderCompiler) -> Result<PipelineReflection, RendererError> {
+        let vert_code = shader_compiler.spirv(&self.vertex_shader, &self.defines, self.shader_options)?.to_vec();
+        let frag_code = shader_compiler.spirv(&self.fragment_shader, &self.defines, self.shader_options)?;
+        self.reflect_code(&vert_code, frag_code)
+    }
+
+    // from the SPIR-V embedded at build time, see ShaderCompiler::spirv
+    pub fn build(
+        &self,
+        device: &ash::Device,
+        deletion_queue: &DeletionQueue,
+        shader_compiler: &mut ShaderCompiler,
+        pipeline_cache: vk::PipelineCache,
+        render_pass: vk::RenderPass,
+    ) -> Result<(Pipeline, PipelineLayout), RendererError> {
+        let vert_code = shader_compiler.spirv(&self.vertex_shader, &self.defines, self.shader_options)?.to_vec();
+        let frag_code = shader_compiler.spirv(&self.fragment_shader, &self.defines, self.shader_options)?;
+        self.build_with(device, deletion_queue, pipeline_cache, render_pass, &vert_code, frag_code)
+    }
+
+    // from the current sources, for hot reloading
+    #[cfg(feature = "hot-reload")]
+    pub fn build_from_source(
+        &self,
+        device: &ash::Device,
+        deletion_queue: &DeletionQueue,
+        shader_compiler: &mut ShaderCompiler,
+        pipeline_cache: vk::PipelineCache,
+        render_pass: vk::RenderPass,
+    ) -> Result<(Pipeline, PipelineLayout), RendererError> {
+        let vert_code = shader_compiler.compile(&self.vertex_shader, &self.defines, self.shader_options)?.to_vec();
+        let frag_code = shader_compiler.compile(&self.fragment_shader, &self.defines, self.shader_options)?;
+        self.build_with(device, deletion_queue, pipeline_cache, render_pass, &vert_code, frag_code)
+    }
+
+    fn reflect_code(&self, vert_code: &[u32], frag_code: &[u32]) -> Result<PipelineReflection, RendererError> {
+        let shader_error = |shaders: String| move |message| RendererError::ShaderInterface {
+            shaders,
+            message,
+        };
+        let vert = reflect::reflect(vert_code).map_err(shader_error(self.vertex_shader.file_name.to_owned()))?;
+        let frag = reflect::reflect(frag_code).map_err(shader_error(self.fragment_shader.file_name.to_owned()))?;
+        PipelineReflection::new(&[vert, frag]).map_err(shader_error(self.shader_names()))
+    }
+
+    fn shader_names(&self) -> String {
+        format!("{}, {}", self.vertex_shader.file_name, self.fragment_shader.file_name)
+    }
+
+    fn build_with(
+        &self,
+        device: &ash::Device,
+        deletion_queue: &DeletionQueue,
+        pipeline_cache: vk::PipelineCache,
+        render_pass: vk::RenderPass,
+        vert_code: &[u32],
+        frag_code: &[u32],
+    ) -> Result<(Pipeline, PipelineLayout), RendererError> {
+        let reflection = self.reflect_code(vert_code, frag_code)?;
+        reflection.check_vertex_layout(&self.vertex_layout).map_err(|message| RendererError::ShaderInterface {
+            shaders: self.vertex_shader.file_name.to_owned(),
+            message,
+        })?;
+        let set_layout_bindings: Vec<_> = self.set_layouts.iter().map(|(_, bindings)| bindings.as_slice()).collect();
+        reflection.check_set_layouts(&set_layout_bindings).map_err(|message| RendererError::ShaderInterface {
+            shaders: self.shader_names(),
+            message,
+        })?;
+
+        let vert_module = create_shader_module(device, vert_code)?;
+        let frag_module = match create_shader_module(device, frag_code) {
+            Ok(frag_module) => frag_module,
+            Err(err) => {
+                unsafe { device.destroy_shader_module(vert_module, None) };
+                return Err(err);
+            }
+        };
+
+        let result = self.build_with_modules(
+            device,
+            deletion_queue,
+            pipeline_cache,
+            render_pass,
+            &reflection.push_constant_ranges,
+            [vert_module, frag_module],
+        );
+
+        unsafe {
+            device.destroy_shader_module(vert_module, None);
+            device.destroy_shader_module(frag_module, None);
+        };
+
+        result
+    }
+
+    fn build_with_modules(
+        &self,
+        device: &ash::Device,
+        deletion_queue: &DeletionQueue,
+        pipeline_cache: vk::PipelineCache,
+        render_pass: vk::RenderPass,
+        push_constant_ranges: &[vk::PushConstantRange],
+        [vert_module, frag_module]: [vk::ShaderModule; 2],
+    ) -> Result<(Pipeline, PipelineLayout), RendererError> {
+        let entry_name = CString::new("main").unwrap();
+        let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vert_module)
+            .name(&entry_name)
+            .build();
+        let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(frag_module)
+            .name(&entry_name)
+            .build();
+
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(&self.dynamic_states)
+            .build();
 
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_attribute_descriptions(&self.vertex_layout.attributes)
+            .vertex_binding_descriptions(&self.vertex_layout.bindings)
+            .build();
+
+        let input_assembly_create = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(self.topology)
+            .primitive_restart_enable(false)
+            .build();
+
+        let viewport_create = vk::PipelineViewportStateCreateInfo::builder()
+            .scissor_count(1)
+            .viewport_count(1)
+            .build();
+
+        let rasterizer_create = vk::PipelineRasterizationStateCreateInfo::builder()
+            .depth_clamp_enable(false)
+            .rasterizer_discard_enable(false)
+            .polygon_mode(self.polygon_mode)
+            .line_width(self.line_width)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .depth_bias_enable(false)
+            .depth_bias_constant_factor(0.0)
+            .depth_bias_clamp(0.0)
+            .depth_bias_slope_factor(0.0)
+            .build();
+
+        let multisampling_create = vk::PipelineMultisampleStateCreateInfo::builder()
+            .sample_shading_enable(false)
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+            .min_sample_shading(1.0)
+            .alpha_to_coverage_enable(false)
+            .alpha_to_one_enable(false)
+            .build();
+
+        let color_blending = vk::PipelineColorBlendStateCreateInfo::builder()
+            .logic_op_enable(false)
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(&self.color_blend_attachments)
+            .blend_constants([0.0, 0.0, 0.0, 0.0])
+            .build();
+
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(self.depth_test)
+            .depth_write_enable(self.depth_write)
+            .depth_compare_op(self.depth_compare_op)
+            .build();
+
+        let layout = {
+            let set_layouts: Vec<_> = self.set_layouts.iter().map(|(handle, _)| *handle).collect();
+            let layout = vk::PipelineLayoutCreateInfo::builder()
+                .set_layouts(&set_layouts)
+                .push_constant_ranges(push_constant_ranges)
+                .build();
+
+            PipelineLayout::new(unsafe { device.create_pipeline_layout(&layout, None)? }, deletion_queue)
+        };
+
+        let stages = [vert_stage, frag_stage];
+        let info = vk::GraphicsPipelineCreateInfo::builder()
+            .dynamic_state(&dynamic_state)
+            .stages(&stages)
+            .input_assembly_state(&input_assembly_create)
+            .viewport_state(&viewport_create)
+            .rasterization_state(&rasterizer_create)
+            .multisample_state(&multisampling_create)
+            .vertex_input_state(&vertex_input_state)
+            .depth_stencil_state(&depth_stencil_state)
+            .color_blend_state(&color_blending)
+            .layout(layout.handle())
+            .render_pass(render_pass)
+            // index of the subpass within render_pass this pipeline is used in
+            .subpass(0)
+            .build();
+        let pipeline = unsafe {
+            device.create_graphics_pipelines(pipeline_cache, &[info], None)
+        };
+
+        match pipeline {
+            Ok(pipelines) => Ok((Pipeline::new(pipelines[0], deletion_queue), layout)),
+            Err((_, result)) => Err(result.into()),
+        }
+    }
+}
+
+fn create_shader_module(device: &ash::Device, code: &[u32]) -> Result<vk::ShaderModule, RendererError> {
     let info = vk::ShaderModuleCreateInfo::builder()
-        .code(&code);
-    unsafe {
+        .code(code);
+    Ok(unsafe {
         device
-            .create_shader_module(&info, None)
-            .unwrap()
+            .create_shader_module(&info, None)?
+    })
+}
+
+// a compute pipeline with its layout, dispatched on the queue its command buffer belongs to
+pub struct ComputePipeline {
+    pub pipeline: Pipeline,
+    pub layout: PipelineLayout,
+    // as declared in the shader, used to round dispatch_invocations up to whole workgroups
+    pub local_size: [u32; 3],
+}
+
+impl ComputePipeline {
+    pub unsafe fn bind(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, descriptor_sets: &[vk::DescriptorSet]) {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline.handle());
+        if !descriptor_sets.is_empty() {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout.handle(),
+                0,
+                descriptor_sets,
+                &[],
+            );
+        }
     }
-}
\ No newline at end of file
+
+    // at offset 0, the whole push constant block of the shader
+    pub unsafe fn push_constants<T: bytemuck::Pod>(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, constants: &T) {
+        device.cmd_push_constants(
+            command_buffer,
+            self.layout.handle(),
+            vk::ShaderStageFlags::COMPUTE,
+            0,
+            bytemuck::bytes_of(constants),
+        );
+    }
+
+    // in workgroups
+    pub unsafe fn dispatch(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, group_count: [u32; 3]) {
+        device.cmd_dispatch(command_buffer, group_count[0], group_count[1], group_count[2]);
+    }
+
+    // enough workgroups for this many invocations, the shader has to skip the ones out of range
+    pub unsafe fn dispatch_invocations(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, invocations: [u32; 3]) {
+        self.dispatch(device, command_buffer, group_count(invocations, self.local_size));
+    }
+}
+
+fn group_count(invocations: [u32; 3], local_size: [u32; 3]) -> [u32; 3] {
+    [0, 1, 2].map(|axis| invocations[axis].div_ceil(local_size[axis]))
+}
+
+// like GraphicsPipelineBuilder, push constant ranges are taken from reflection
+#[derive(Clone)]
+pub struct ComputePipelineBuilder {
+    shader: EmbeddedShader,
+    defines: Defines,
+    shader_options: ShaderOptions,
+    // with their bindings, to check them against the shader
+    set_layouts: Vec<(vk::DescriptorSetLayout, Vec<vk::DescriptorSetLayoutBinding>)>,
+    specialization_entries: Vec<vk::SpecializationMapEntry>,
+    specialization_data: Vec<u8>,
+    local_size: Option<[u32; 3]>,
+}
+
+impl ComputePipelineBuilder {
+    pub fn new(shader: EmbeddedShader) -> Self {
+        Self {
+            shader,
+            defines: Defines::new(),
+            shader_options: ShaderOptions::default(),
+            set_layouts: Vec::new(),
+            specialization_entries: Vec::new(),
+            specialization_data: Vec::new(),
+            local_size: None,
+        }
+    }
+
+    // sets `layout(constant_id = constant_id)`, unlike defines no permutation is compiled for it
+    pub fn specialize<T: bytemuck::Pod>(mut self, constant_id: u32, value: T) -> Self {
+        let bytes = bytemuck::bytes_of(&value);
+        self.specialization_entries.push(vk::SpecializationMapEntry {
+            constant_id,
+            offset: self.specialization_data.len() as u32,
+            size: bytes.len(),
+        });
+        self.specialization_data.extend_from_slice(bytes);
+        self
+    }
+
+    // when the shader takes its workgroup size from specialization constants, as reflection
+    // only sees the declared one
+    pub fn local_size(mut self, local_size: [u32; 3]) -> Self {
+        self.local_size = Some(local_size);
+        self
+    }
+
+    // selects the permutation of the shader compiled with these defines,
+    // it is compiled when building unless the shader declares it
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.defines.insert(name.to_owned(), value.map(str::to_owned));
+        self
+    }
+
+    // instead of the cargo profile's, the shader is then compiled when building
+    pub fn optimization(mut self, optimization: shaderc::OptimizationLevel) -> Self {
+        self.shader_options.optimization = Some(optimization);
+        self
+    }
+
+    // instead of the cargo profile's, the shader is then compiled when building
+    pub fn debug_info(mut self, debug_info: bool) -> Self {
+        self.shader_options.debug_info = Some(debug_info);
+        self
+    }
+
+    // in set order, usually created from the bindings in reflect()
+    pub fn set_layouts(mut self, set_layouts: &[&DescriptorSetLayout]) -> Self {
+        self.set_layouts = set_layouts
+            .iter()
+            .map(|layout| (layout.handle(), layout.bindings().to_vec()))
+            .collect();
+        self
+    }
+
+    pub fn reflect(&self, shader_compiler: &mut ShaderCompiler) -> Result<PipelineReflection, RendererError> {
+        self.reflect_code(shader_compiler.spirv(&self.shader, &self.defines, self.shader_options)?)
+    }
+
+    // from the SPIR-V embedded at build time, see ShaderCompiler::spirv
+    pub fn build(
+        &self,
+        device: &ash::Device,
+        deletion_queue: &DeletionQueue,
+        shader_compiler: &mut ShaderCompiler,
+        pipeline_cache: vk::PipelineCache,
+    ) -> Result<ComputePipeline, RendererError> {
+        let code = shader_compiler.spirv(&self.shader, &self.defines, self.shader_options)?;
+        self.build_with(device, deletion_queue, pipeline_cache, code)
+    }
+
+    // from the current source, for hot reloading
+    #[cfg(feature = "hot-reload")]
+    pub fn build_from_source(
+        &self,
+        device: &ash::Device,
+        deletion_queue: &DeletionQueue,
+        shader_compiler: &mut ShaderCompiler,
+        pipeline_cache: vk::PipelineCache,
+    ) -> Result<ComputePipeline, RendererError> {
+        let code = shader_compiler.compile(&self.shader, &self.defines, self.shader_options)?;
+        self.build_with(device, deletion_queue, pipeline_cache, code)
+    }
+
+    fn reflect_code(&self, code: &[u32]) -> Result<PipelineReflection, RendererError> {
+        let shader_error = |message| RendererError::ShaderInterface {
+            shaders: self.shader.file_name.to_owned(),
+            message,
+        };
+        PipelineReflection::new(&[reflect::reflect(code).map_err(shader_error)?]).map_err(shader_error)
+    }
+
+    fn build_with(
+        &self,
+        device: &ash::Device,
+        deletion_queue: &DeletionQueue,
+        pipeline_cache: vk::PipelineCache,
+        code: &[u32],
+    ) -> Result<ComputePipeline, RendererError> {
+        let reflection = self.reflect_code(code)?;
+        let set_layout_bindings: Vec<_> = self.set_layouts.iter().map(|(_, bindings)| bindings.as_slice()).collect();
+        reflection.check_set_layouts(&set_layout_bindings).map_err(|message| RendererError::ShaderInterface {
+            shaders: self.shader.file_name.to_owned(),
+            message,
+        })?;
+
+        let layout = {
+            let set_layouts: Vec<_> = self.set_layouts.iter().map(|(handle, _)| *handle).collect();
+            let layout = vk::PipelineLayoutCreateInfo::builder()
+                .set_layouts(&set_layouts)
+                .push_constant_ranges(&reflection.push_constant_ranges)
+                .build();
+
+            PipelineLayout::new(unsafe { device.create_pipeline_layout(&layout, None)? }, deletion_queue)
+        };
+
+        let module = create_shader_module(device, code)?;
+        let entry_name = CString::new("main").unwrap();
+        let specialization = vk::SpecializationInfo::builder()
+            .map_entries(&self.specialization_entries)
+            .data(&self.specialization_data)
+            .build();
+        let info = vk::ComputePipelineCreateInfo::builder()
+            .stage(vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(module)
+                .name(&entry_name)
+                .specialization_info(&specialization)
+                .build())
+            .layout(layout.handle())
+            .build();
+        let pipeline = unsafe { device.create_compute_pipelines(pipeline_cache, &[info], None) };
+        unsafe { device.destroy_shader_module(module, None) };
+
+        match pipeline {
+            Ok(pipelines) => Ok(ComputePipeline {
+                pipeline: Pipeline::new(pipelines[0], deletion_queue),
+                layout,
+                local_size: self.local_size.or(reflection.local_size).unwrap_or([1; 3]),
+            }),
+            Err((_, result)) => Err(result.into()),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+use ash::vk;
+
+use super::RendererError;
+
+// size of VkPipelineCacheHeaderVersionOne
+const HEADER_SIZE: usize = 32;
+
+// the data starts with a little endian header identifying the device that produced it,
+// data from another device or driver would be ignored or rejected by the driver anyway
+fn header_matches(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    read_u32(0) as usize >= HEADER_SIZE
+        && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..32] == properties.pipeline_cache_uuid
+}
+
+// starts from an empty cache when the file is missing, unreadable or from another device
+pub fn load_pipeline_cache(
+    device: &ash::Device,
+    properties: &vk::PhysicalDeviceProperties,
+    path: Option<&Path>,
+) -> Result<vk::PipelineCache, RendererError> {
+    let data = match path.map(std::fs::read) {
+        Some(Ok(data)) if header_matches(&data, properties) => {
+            log::info!("Loaded pipeline cache from {}", path.unwrap().display());
+            data
+        }
+        Some(Ok(_)) => {
+            log::info!("Ignoring pipeline cache {} made by another device or driver", path.unwrap().display());
+            Vec::new()
+        }
+        Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => {
+            log::warn!("Could not read pipeline cache {}: {}", path.unwrap().display(), err);
+            Vec::new()
+        }
+        _ => Vec::new(),
+    };
+
+    let info = vk::PipelineCacheCreateInfo::builder()
+        .initial_data(&data);
+    Ok(unsafe { device.create_pipeline_cache(&info, None)? })
+}
+
+pub fn save_pipeline_cache(
+    device: &ash::Device,
+    pipeline_cache: vk::PipelineCache,
+    path: &Path,
+) -> Result<(), RendererError> {
+    let data = unsafe { device.get_pipeline_cache_data(pipeline_cache)? };
+
+    // written next to the old file first so a crash never leaves a truncated cache behind
+    let io_error = |source| RendererError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let temp_path = path.with_extension("tmp");
+    std::fs::write(&temp_path, &data).map_err(io_error)?;
+    std::fs::rename(&temp_path, path).map_err(io_error)?;
+
+    log::info!("Saved pipeline cache to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2204,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    fn header(vendor_id: u32, device_id: u32, uuid: [u8; vk::UUID_SIZE]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&vendor_id.to_le_bytes());
+        data.extend_from_slice(&device_id.to_le_bytes());
+        data.extend_from_slice(&uuid);
+        // driver specific payload
+        data.extend_from_slice(&[1, 2, 3]);
+        data
+    }
+
+    #[test]
+    fn header_must_match_device() {
+        let properties = properties();
+        assert!(header_matches(&header(0x10de, 0x2204, [7; vk::UUID_SIZE]), &properties));
+        assert!(!header_matches(&header(0x1002, 0x2204, [7; vk::UUID_SIZE]), &properties));
+        assert!(!header_matches(&header(0x10de, 0x2205, [7; vk::UUID_SIZE]), &properties));
+        assert!(!header_matches(&header(0x10de, 0x2204, [8; vk::UUID_SIZE]), &properties));
+        assert!(!header_matches(&header(0x10de, 0x2204, [7; vk::UUID_SIZE])[..HEADER_SIZE - 1], &properties));
+        assert!(!header_matches(&[], &properties));
+    }
+}
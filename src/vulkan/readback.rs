@@ -0,0 +1,126 @@
+use ash::vk;
+
+use super::{
+    allocator::Allocator,
+    barrier::{self, Access},
+    resource::{Buffer, DeletionQueue},
+    RendererError,
+};
+
+// host visible copy of a color image, used for screenshots and headless readback
+pub struct ReadbackBuffer {
+    pub buffer: Buffer,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+}
+
+pub fn create_readback_buffer(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    deletion_queue: &DeletionQueue,
+    extent: vk::Extent2D,
+    format: vk::Format,
+) -> Result<ReadbackBuffer, RendererError> {
+    let buffer = super::buffer::create_buffer(
+        device,
+        allocator,
+        deletion_queue,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        (extent.width * extent.height * 4) as vk::DeviceSize,
+    )?;
+
+    Ok(ReadbackBuffer {
+        buffer,
+        extent,
+        format,
+    })
+}
+
+impl ReadbackBuffer {
+    // the image is transitioned to TRANSFER_SRC_OPTIMAL for the copy and back to `layout` afterwards
+    pub unsafe fn record_copy(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        layout: vk::ImageLayout,
+    ) {
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        if layout != vk::ImageLayout::TRANSFER_SRC_OPTIMAL {
+            barrier::image_barrier(
+                device,
+                command_buffer,
+                image,
+                subresource_range,
+                (layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+                Access::COLOR_ATTACHMENT_WRITE,
+                Access::TRANSFER_READ,
+            );
+        }
+
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            self.buffer.handle(),
+            &[
+                vk::BufferImageCopy::builder()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width: self.extent.width,
+                        height: self.extent.height,
+                        depth: 1,
+                    })
+                    .build()
+            ],
+        );
+
+        if layout != vk::ImageLayout::TRANSFER_SRC_OPTIMAL {
+            barrier::image_barrier(
+                device,
+                command_buffer,
+                image,
+                subresource_range,
+                (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, layout),
+                Access::TRANSFER_READ,
+                Access::NONE_AFTER,
+            );
+        }
+    }
+
+    // tightly packed RGBA8 rows, top row first,
+    // must only be called once the copy has finished executing
+    pub unsafe fn read_rgba8(&self) -> Result<Vec<u8>, RendererError> {
+        let size = (self.extent.width * self.extent.height * 4) as usize;
+        let mut pixels = vec![0; size];
+
+        let ptr = self.buffer.allocation().mapped_ptr().expect("readback memory is host visible");
+        (ptr as *const u8).copy_to_nonoverlapping(pixels.as_mut_ptr(), size);
+
+        match self.format {
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => {}
+            // choose_swapchain_format prefers B8G8R8A8_UNORM
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => {
+                for pixel in pixels.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            format => return Err(RendererError::UnsupportedFormat(format)),
+        }
+
+        Ok(pixels)
+    }
+}
@@ -0,0 +1,775 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use super::vertex::VertexLayout;
+
+// just enough of a SPIR-V parser to find what a shader expects to be bound:
+// descriptors, push constants and, for vertex shaders, the vertex inputs,
+// for compute shaders the workgroup size
+
+const MAGIC: u32 = 0x0723_0203;
+
+mod op {
+    pub const ENTRY_POINT: u32 = 15;
+    pub const EXECUTION_MODE: u32 = 16;
+    pub const TYPE_INT: u32 = 21;
+    pub const TYPE_FLOAT: u32 = 22;
+    pub const TYPE_VECTOR: u32 = 23;
+    pub const TYPE_MATRIX: u32 = 24;
+    pub const TYPE_IMAGE: u32 = 25;
+    pub const TYPE_SAMPLER: u32 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u32 = 27;
+    pub const TYPE_ARRAY: u32 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u32 = 29;
+    pub const TYPE_STRUCT: u32 = 30;
+    pub const TYPE_POINTER: u32 = 32;
+    pub const CONSTANT: u32 = 43;
+    pub const VARIABLE: u32 = 59;
+    pub const DECORATE: u32 = 71;
+    pub const MEMBER_DECORATE: u32 = 72;
+}
+
+mod decoration {
+    pub const BLOCK: u32 = 2;
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+mod execution_mode {
+    pub const LOCAL_SIZE: u32 = 17;
+}
+
+mod storage_class {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumericType {
+    Float,
+    Sint,
+    Uint,
+}
+
+impl NumericType {
+    // the type a shader sees when reading an attribute of `format`
+    pub fn of_format(format: vk::Format) -> Option<Self> {
+        let name = format!("{:?}", format);
+        if name.ends_with("_UINT") {
+            Some(Self::Uint)
+        } else if name.ends_with("_SINT") {
+            Some(Self::Sint)
+        } else if ["_SFLOAT", "_UFLOAT", "_UNORM", "_SNORM", "_USCALED", "_SSCALED", "_SRGB"]
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+        {
+            Some(Self::Float)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexInput {
+    pub location: u32,
+    pub numeric_type: NumericType,
+    pub components: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub bindings: Vec<DescriptorBinding>,
+    // offset and size
+    pub push_constants: Option<(u32, u32)>,
+    // sorted by location, empty unless a vertex shader
+    pub vertex_inputs: Vec<VertexInput>,
+    // the declared local_size of a compute shader, specialization constants are not applied
+    pub local_size: Option<[u32; 3]>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Scalar(NumericType, u32),
+    Vector(Box<Type>, u32),
+    // column type and count
+    Matrix(Box<Type>, u32),
+    Image {
+        dim: u32,
+        sampled: u32,
+    },
+    Sampler,
+    SampledImage,
+    Array(u32, u32),
+    RuntimeArray,
+    Struct(Vec<u32>),
+    // pointee
+    Pointer(u32),
+}
+
+#[derive(Default)]
+struct Decorations {
+    block: bool,
+    buffer_block: bool,
+    built_in: bool,
+    location: Option<u32>,
+    set: Option<u32>,
+    binding: Option<u32>,
+    array_stride: Option<u32>,
+}
+
+#[derive(Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+}
+
+#[derive(Default)]
+struct Module {
+    stage: Option<vk::ShaderStageFlags>,
+    local_size: Option<[u32; 3]>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    // id, pointer type and storage class
+    variables: Vec<(u32, u32, u32)>,
+}
+
+pub fn reflect(spirv: &[u32]) -> Result<ShaderReflection, String> {
+    let module = parse(spirv)?;
+    let stage = module.stage.ok_or("no supported entry point")?;
+
+    let mut bindings = Vec::new();
+    let mut push_constants = None;
+    let mut vertex_inputs = Vec::new();
+
+    for &(id, pointer_type, storage) in &module.variables {
+        let decorations = module.decorations.get(&id);
+        let pointee = match module.types.get(&pointer_type) {
+            Some(&Type::Pointer(pointee)) => pointee,
+            _ => return Err(format!("variable %{} is not a pointer", id)),
+        };
+
+        match storage {
+            storage_class::UNIFORM_CONSTANT | storage_class::UNIFORM | storage_class::STORAGE_BUFFER => {
+                let (set, binding) = match decorations {
+                    Some(&Decorations { set: Some(set), binding: Some(binding), .. }) => (set, binding),
+                    _ => return Err(format!("resource %{} has no set and binding", id)),
+                };
+                let (element, count) = module.unwrap_array(pointee)?;
+                bindings.push(DescriptorBinding {
+                    set,
+                    binding,
+                    descriptor_type: module.descriptor_type(element, storage)?,
+                    count,
+                });
+            }
+            storage_class::PUSH_CONSTANT => push_constants = Some(module.push_constant_range(pointee)?),
+            storage_class::INPUT if stage == vk::ShaderStageFlags::VERTEX => {
+                let Some(decorations) = decorations.filter(|decorations| !decorations.built_in) else {
+                    continue;
+                };
+                let location = decorations.location.ok_or_else(|| format!("input %{} has no location", id))?;
+                // a matrix takes one location per column
+                let (column, columns) = match module.ty(pointee)? {
+                    Type::Matrix(column, columns) => (&**column, *columns),
+                    ty => (ty, 1),
+                };
+                let (numeric_type, components) = match column {
+                    Type::Scalar(numeric_type, _) => (*numeric_type, 1),
+                    Type::Vector(scalar, components) => match &**scalar {
+                        Type::Scalar(numeric_type, _) => (*numeric_type, *components),
+                        _ => return Err(format!("unsupported type of input %{}", id)),
+                    },
+                    _ => return Err(format!("unsupported type of input %{}", id)),
+                };
+                vertex_inputs.extend((0..columns).map(|column| VertexInput {
+                    location: location + column,
+                    numeric_type,
+                    components,
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    bindings.sort_by_key(|binding| (binding.set, binding.binding));
+    vertex_inputs.sort_by_key(|input| input.location);
+
+    Ok(ShaderReflection {
+        stage,
+        bindings,
+        push_constants,
+        vertex_inputs,
+        local_size: module.local_size,
+    })
+}
+
+fn parse(spirv: &[u32]) -> Result<Module, String> {
+    if spirv.len() < 5 || spirv[0] != MAGIC {
+        return Err("not SPIR-V".to_owned());
+    }
+
+    let mut module = Module::default();
+    let mut words = &spirv[5..];
+    while !words.is_empty() {
+        let word_count = (words[0] >> 16) as usize;
+        let opcode = words[0] & 0xffff;
+        if word_count == 0 || word_count > words.len() {
+            return Err("truncated instruction".to_owned());
+        }
+        let operands = &words[1..word_count];
+        words = &words[word_count..];
+
+        let operand = |index: usize| operands.get(index).copied().ok_or_else(|| format!("truncated instruction {}", opcode));
+        match opcode {
+            op::ENTRY_POINT => {
+                // vertex, fragment and gl compute execution models
+                module.stage = match operand(0)? {
+                    0 => Some(vk::ShaderStageFlags::VERTEX),
+                    4 => Some(vk::ShaderStageFlags::FRAGMENT),
+                    5 => Some(vk::ShaderStageFlags::COMPUTE),
+                    _ => module.stage,
+                };
+            }
+            op::EXECUTION_MODE if operand(1)? == execution_mode::LOCAL_SIZE => {
+                module.local_size = Some([operand(2)?, operand(3)?, operand(4)?]);
+            }
+            op::TYPE_INT => {
+                let numeric_type = if operand(2)? == 1 { NumericType::Sint } else { NumericType::Uint };
+                module.types.insert(operand(0)?, Type::Scalar(numeric_type, operand(1)?));
+            }
+            op::TYPE_FLOAT => {
+                module.types.insert(operand(0)?, Type::Scalar(NumericType::Float, operand(1)?));
+            }
+            op::TYPE_VECTOR | op::TYPE_MATRIX => {
+                let component = Box::new(module.ty(operand(1)?)?.clone());
+                let ty = if opcode == op::TYPE_VECTOR {
+                    Type::Vector(component, operand(2)?)
+                } else {
+                    Type::Matrix(component, operand(2)?)
+                };
+                module.types.insert(operand(0)?, ty);
+            }
+            op::TYPE_IMAGE => {
+                module.types.insert(operand(0)?, Type::Image {
+                    dim: operand(2)?,
+                    sampled: operand(6)?,
+                });
+            }
+            op::TYPE_SAMPLER => {
+                module.types.insert(operand(0)?, Type::Sampler);
+            }
+            op::TYPE_SAMPLED_IMAGE => {
+                module.types.insert(operand(0)?, Type::SampledImage);
+            }
+            op::TYPE_ARRAY => {
+                let length = *module.constants.get(&operand(2)?).ok_or("array length is not a constant")?;
+                module.types.insert(operand(0)?, Type::Array(operand(1)?, length));
+            }
+            op::TYPE_RUNTIME_ARRAY => {
+                module.types.insert(operand(0)?, Type::RuntimeArray);
+            }
+            op::TYPE_STRUCT => {
+                module.types.insert(operand(0)?, Type::Struct(operands[1..].to_vec()));
+            }
+            op::TYPE_POINTER => {
+                module.types.insert(operand(0)?, Type::Pointer(operand(2)?));
+            }
+            op::CONSTANT => {
+                // only 32 bit integer constants are of interest, as array lengths
+                module.constants.insert(operand(1)?, operand(2)?);
+            }
+            op::VARIABLE => {
+                module.variables.push((operand(1)?, operand(0)?, operand(2)?));
+            }
+            op::DECORATE => {
+                let decorations = module.decorations.entry(operand(0)?).or_default();
+                match operand(1)? {
+                    decoration::BLOCK => decorations.block = true,
+                    decoration::BUFFER_BLOCK => decorations.buffer_block = true,
+                    decoration::BUILT_IN => decorations.built_in = true,
+                    decoration::LOCATION => decorations.location = Some(operand(2)?),
+                    decoration::DESCRIPTOR_SET => decorations.set = Some(operand(2)?),
+                    decoration::BINDING => decorations.binding = Some(operand(2)?),
+                    decoration::ARRAY_STRIDE => decorations.array_stride = Some(operand(2)?),
+                    _ => {}
+                }
+            }
+            op::MEMBER_DECORATE => {
+                let decorations = module.member_decorations.entry((operand(0)?, operand(1)?)).or_default();
+                match operand(2)? {
+                    decoration::OFFSET => decorations.offset = Some(operand(3)?),
+                    decoration::MATRIX_STRIDE => decorations.matrix_stride = Some(operand(3)?),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(module)
+}
+
+impl Module {
+    fn ty(&self, id: u32) -> Result<&Type, String> {
+        self.types.get(&id).ok_or_else(|| format!("unknown type %{}", id))
+    }
+
+    fn unwrap_array(&self, id: u32) -> Result<(u32, u32), String> {
+        match self.ty(id)? {
+            &Type::Array(element, length) => Ok((element, length)),
+            Type::RuntimeArray => Err("runtime sized descriptor arrays are not supported".to_owned()),
+            _ => Ok((id, 1)),
+        }
+    }
+
+    fn descriptor_type(&self, id: u32, storage: u32) -> Result<vk::DescriptorType, String> {
+        let decorations = self.decorations.get(&id);
+        Ok(match (storage, self.ty(id)?) {
+            (storage_class::STORAGE_BUFFER, _) => vk::DescriptorType::STORAGE_BUFFER,
+            (storage_class::UNIFORM, _) if decorations.is_some_and(|decorations| decorations.buffer_block) => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            (storage_class::UNIFORM, _) if decorations.is_some_and(|decorations| decorations.block) => {
+                vk::DescriptorType::UNIFORM_BUFFER
+            }
+            (_, Type::SampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (_, Type::Sampler) => vk::DescriptorType::SAMPLER,
+            // dims 5 and 6 are Buffer and SubpassData, sampled 1 means sampled and 2 storage
+            (_, &Type::Image { dim: 5, sampled: 1 }) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            (_, &Type::Image { dim: 5, .. }) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            (_, &Type::Image { dim: 6, .. }) => vk::DescriptorType::INPUT_ATTACHMENT,
+            (_, &Type::Image { sampled: 2, .. }) => vk::DescriptorType::STORAGE_IMAGE,
+            (_, Type::Image { .. }) => vk::DescriptorType::SAMPLED_IMAGE,
+            _ => return Err(format!("unsupported resource type %{}", id)),
+        })
+    }
+
+    // spans from the first to the end of the last member actually declared
+    fn push_constant_range(&self, id: u32) -> Result<(u32, u32), String> {
+        let Type::Struct(members) = self.ty(id)? else {
+            return Err("push constants are not a block".to_owned());
+        };
+
+        let mut start = u32::MAX;
+        let mut end = 0;
+        for (index, &member) in members.iter().enumerate() {
+            let decorations = self.member_decorations.get(&(id, index as u32));
+            let offset = decorations
+                .and_then(|decorations| decorations.offset)
+                .ok_or("push constant member without offset")?;
+            let matrix_stride = decorations.and_then(|decorations| decorations.matrix_stride);
+            start = start.min(offset);
+            end = end.max(offset + self.size(member, matrix_stride)?);
+        }
+        if members.is_empty() {
+            return Err("empty push constant block".to_owned());
+        }
+
+        // offset and size of a range must be multiples of 4
+        let start = start / 4 * 4;
+        Ok((start, (end - start).div_ceil(4) * 4))
+    }
+
+    fn size(&self, id: u32, matrix_stride: Option<u32>) -> Result<u32, String> {
+        Ok(match self.ty(id)? {
+            Type::Scalar(_, width) => width / 8,
+            Type::Vector(scalar, components) => match &**scalar {
+                Type::Scalar(_, width) => width / 8 * components,
+                _ => return Err("vector of non scalars".to_owned()),
+            },
+            Type::Matrix(column, columns) => match (&**column, matrix_stride) {
+                (_, Some(stride)) => stride * columns,
+                (Type::Vector(scalar, components), None) => match &**scalar {
+                    Type::Scalar(_, width) => width / 8 * components * columns,
+                    _ => return Err("matrix of non scalars".to_owned()),
+                },
+                _ => return Err("matrix of non vectors".to_owned()),
+            },
+            &Type::Array(_, length) => {
+                let stride = self.decorations
+                    .get(&id)
+                    .and_then(|decorations| decorations.array_stride)
+                    .ok_or("array without stride")?;
+                stride * length
+            }
+            Type::Struct(members) => {
+                let mut end = 0;
+                for (index, &member) in members.iter().enumerate() {
+                    let decorations = self.member_decorations.get(&(id, index as u32));
+                    let offset = decorations.and_then(|decorations| decorations.offset).ok_or("struct member without offset")?;
+                    end = end.max(offset + self.size(member, decorations.and_then(|decorations| decorations.matrix_stride))?);
+                }
+                end
+            }
+            _ => return Err(format!("type %{} has no size", id)),
+        })
+    }
+}
+
+// the combined interface of the stages of one pipeline
+#[derive(Clone, Debug, Default)]
+pub struct PipelineReflection {
+    // indexed by set, gaps left empty
+    pub sets: Vec<Vec<vk::DescriptorSetLayoutBinding>>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    pub vertex_inputs: Vec<VertexInput>,
+    // of the compute stage
+    pub local_size: Option<[u32; 3]>,
+}
+
+impl PipelineReflection {
+    pub fn new(stages: &[ShaderReflection]) -> Result<Self, String> {
+        let mut reflection = Self::default();
+        for stage in stages {
+            for binding in &stage.bindings {
+                let set = binding.set as usize;
+                if reflection.sets.len() <= set {
+                    reflection.sets.resize(set + 1, Vec::new());
+                }
+
+                let bindings = &mut reflection.sets[set];
+                match bindings.iter_mut().find(|existing| existing.binding == binding.binding) {
+                    Some(existing) if existing.descriptor_type != binding.descriptor_type
+                        || existing.descriptor_count != binding.count => {
+                        return Err(format!(
+                            "set {} binding {} is declared as {:?}[{}] and {:?}[{}] by different stages",
+                            binding.set, binding.binding,
+                            existing.descriptor_type, existing.descriptor_count,
+                            binding.descriptor_type, binding.count,
+                        ));
+                    }
+                    Some(existing) => existing.stage_flags |= stage.stage,
+                    None => bindings.push(vk::DescriptorSetLayoutBinding {
+                        binding: binding.binding,
+                        descriptor_type: binding.descriptor_type,
+                        descriptor_count: binding.count,
+                        stage_flags: stage.stage,
+                        ..Default::default()
+                    }),
+                }
+            }
+
+            // one range per stage, each stage may only appear in one range
+            if let Some((offset, size)) = stage.push_constants {
+                reflection.push_constant_ranges.push(vk::PushConstantRange {
+                    stage_flags: stage.stage,
+                    offset,
+                    size,
+                });
+            }
+
+            if stage.stage == vk::ShaderStageFlags::VERTEX {
+                reflection.vertex_inputs = stage.vertex_inputs.clone();
+            }
+            if stage.stage == vk::ShaderStageFlags::COMPUTE {
+                reflection.local_size = stage.local_size;
+            }
+        }
+
+        for bindings in &mut reflection.sets {
+            bindings.sort_by_key(|binding| binding.binding);
+        }
+        Ok(reflection)
+    }
+
+    // every input must be fed by an attribute of the same numeric type,
+    // attributes the shader does not read are allowed
+    pub fn check_vertex_layout(&self, vertex_layout: &VertexLayout) -> Result<(), String> {
+        for input in &self.vertex_inputs {
+            let attribute = vertex_layout.attributes
+                .iter()
+                .find(|attribute| attribute.location == input.location)
+                .ok_or_else(|| format!(
+                    "the vertex shader reads location {} ({:?} x{}) but the vertex layout has no attribute there",
+                    input.location, input.numeric_type, input.components,
+                ))?;
+            if NumericType::of_format(attribute.format) != Some(input.numeric_type) {
+                return Err(format!(
+                    "the vertex shader reads location {} as {:?} but the vertex layout provides {:?}",
+                    input.location, input.numeric_type, attribute.format,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // every binding the shaders use must be in the layout of its set with the same type,
+    // a dynamic buffer in the layout matches the plain one the shader declares
+    pub fn check_set_layouts(&self, set_layouts: &[&[vk::DescriptorSetLayoutBinding]]) -> Result<(), String> {
+        for (set, bindings) in self.sets.iter().enumerate() {
+            for binding in bindings {
+                let provided = set_layouts
+                    .get(set)
+                    .and_then(|layout| layout.iter().find(|provided| provided.binding == binding.binding))
+                    .ok_or_else(|| format!(
+                        "the shaders use set {} binding {} ({:?}) which is missing from the set layouts",
+                        set, binding.binding, binding.descriptor_type,
+                    ))?;
+
+                let provided_type = match provided.descriptor_type {
+                    vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC => vk::DescriptorType::UNIFORM_BUFFER,
+                    vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => vk::DescriptorType::STORAGE_BUFFER,
+                    descriptor_type => descriptor_type,
+                };
+                if provided_type != binding.descriptor_type {
+                    return Err(format!(
+                        "set {} binding {} is {:?} in the shaders but {:?} in the set layout",
+                        set, binding.binding, binding.descriptor_type, provided.descriptor_type,
+                    ));
+                }
+                if provided.descriptor_count < binding.descriptor_count {
+                    return Err(format!(
+                        "set {} binding {} holds {} descriptors in the shaders but {} in the set layout",
+                        set, binding.binding, binding.descriptor_count, provided.descriptor_count,
+                    ));
+                }
+                if !provided.stage_flags.contains(binding.stage_flags) {
+                    return Err(format!(
+                        "set {} binding {} is used by {:?} but only visible to {:?}",
+                        set, binding.binding, binding.stage_flags, provided.stage_flags,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    // the interface of main.vert with an added push constant block, as glslang lays it out
+    fn vertex_module() -> Vec<u32> {
+        const FLOAT: u32 = 1;
+        const VEC2: u32 = 2;
+        const VEC3: u32 = 3;
+        const VEC4: u32 = 4;
+        const MAT4: u32 = 5;
+        const UBO: u32 = 6;
+        const PUSH: u32 = 7;
+        const UBO_PTR: u32 = 8;
+        const PUSH_PTR: u32 = 9;
+        const VEC3_INPUT_PTR: u32 = 10;
+        const VEC2_INPUT_PTR: u32 = 11;
+        const MAT4_INPUT_PTR: u32 = 12;
+        const U_VIEW: u32 = 20;
+        const PUSHED: u32 = 21;
+        const V_POSITION: u32 = 22;
+        const V_TEX_COORD: u32 = 23;
+        const V_TRANSFORM: u32 = 24;
+
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 30, 0];
+        for (opcode, operands) in [
+            (op::ENTRY_POINT, &[0, 100, 0x6e69_616d, 0][..]),
+            (op::DECORATE, &[UBO, decoration::BLOCK]),
+            (op::DECORATE, &[U_VIEW, decoration::DESCRIPTOR_SET, 0]),
+            (op::DECORATE, &[U_VIEW, decoration::BINDING, 0]),
+            (op::DECORATE, &[PUSH, decoration::BLOCK]),
+            (op::MEMBER_DECORATE, &[PUSH, 0, decoration::OFFSET, 16]),
+            (op::MEMBER_DECORATE, &[PUSH, 1, decoration::OFFSET, 32]),
+            (op::DECORATE, &[V_POSITION, decoration::LOCATION, 0]),
+            (op::DECORATE, &[V_TEX_COORD, decoration::LOCATION, 2]),
+            (op::DECORATE, &[V_TRANSFORM, decoration::LOCATION, 3]),
+            (op::TYPE_FLOAT, &[FLOAT, 32]),
+            (op::TYPE_VECTOR, &[VEC2, FLOAT, 2]),
+            (op::TYPE_VECTOR, &[VEC3, FLOAT, 3]),
+            (op::TYPE_VECTOR, &[VEC4, FLOAT, 4]),
+            (op::TYPE_MATRIX, &[MAT4, VEC4, 4]),
+            (op::TYPE_STRUCT, &[UBO, VEC4, VEC4, VEC4, FLOAT]),
+            (op::TYPE_STRUCT, &[PUSH, VEC4, VEC3]),
+            (op::TYPE_POINTER, &[UBO_PTR, storage_class::UNIFORM, UBO]),
+            (op::TYPE_POINTER, &[PUSH_PTR, storage_class::PUSH_CONSTANT, PUSH]),
+            (op::TYPE_POINTER, &[VEC3_INPUT_PTR, storage_class::INPUT, VEC3]),
+            (op::TYPE_POINTER, &[VEC2_INPUT_PTR, storage_class::INPUT, VEC2]),
+            (op::TYPE_POINTER, &[MAT4_INPUT_PTR, storage_class::INPUT, MAT4]),
+            (op::VARIABLE, &[UBO_PTR, U_VIEW, storage_class::UNIFORM]),
+            (op::VARIABLE, &[PUSH_PTR, PUSHED, storage_class::PUSH_CONSTANT]),
+            (op::VARIABLE, &[VEC3_INPUT_PTR, V_POSITION, storage_class::INPUT]),
+            (op::VARIABLE, &[VEC2_INPUT_PTR, V_TEX_COORD, storage_class::INPUT]),
+            (op::VARIABLE, &[MAT4_INPUT_PTR, V_TRANSFORM, storage_class::INPUT]),
+        ] {
+            words.extend(instruction(opcode, operands));
+        }
+        words
+    }
+
+    fn fragment_module(sampler_binding: u32) -> Vec<u32> {
+        const FLOAT: u32 = 1;
+        const IMAGE: u32 = 2;
+        const SAMPLED_IMAGE: u32 = 3;
+        const SAMPLER_PTR: u32 = 4;
+        const U_SAMPLER: u32 = 10;
+
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 20, 0];
+        for (opcode, operands) in [
+            (op::ENTRY_POINT, &[4, 100, 0x6e69_616d, 0][..]),
+            (op::DECORATE, &[U_SAMPLER, decoration::DESCRIPTOR_SET, 0]),
+            (op::DECORATE, &[U_SAMPLER, decoration::BINDING, sampler_binding]),
+            (op::TYPE_FLOAT, &[FLOAT, 32]),
+            (op::TYPE_IMAGE, &[IMAGE, FLOAT, 1, 0, 0, 0, 1, 0]),
+            (op::TYPE_SAMPLED_IMAGE, &[SAMPLED_IMAGE, IMAGE]),
+            (op::TYPE_POINTER, &[SAMPLER_PTR, storage_class::UNIFORM_CONSTANT, SAMPLED_IMAGE]),
+            (op::VARIABLE, &[SAMPLER_PTR, U_SAMPLER, storage_class::UNIFORM_CONSTANT]),
+        ] {
+            words.extend(instruction(opcode, operands));
+        }
+        words
+    }
+
+    // a storage buffer and push constants as in cull.comp
+    fn compute_module() -> Vec<u32> {
+        const UINT: u32 = 1;
+        const RUNTIME_ARRAY: u32 = 2;
+        const BUFFER: u32 = 3;
+        const PUSH: u32 = 4;
+        const BUFFER_PTR: u32 = 5;
+        const PUSH_PTR: u32 = 6;
+        const MAIN: u32 = 10;
+        const U_COUNTS: u32 = 11;
+        const PUSHED: u32 = 12;
+
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 20, 0];
+        for (opcode, operands) in [
+            (op::ENTRY_POINT, &[5, MAIN, 0x6e69_616d, 0][..]),
+            (op::EXECUTION_MODE, &[MAIN, execution_mode::LOCAL_SIZE, 64, 2, 1]),
+            (op::DECORATE, &[RUNTIME_ARRAY, decoration::ARRAY_STRIDE, 4]),
+            (op::DECORATE, &[BUFFER, decoration::BLOCK]),
+            (op::MEMBER_DECORATE, &[BUFFER, 0, decoration::OFFSET, 0]),
+            (op::DECORATE, &[U_COUNTS, decoration::DESCRIPTOR_SET, 0]),
+            (op::DECORATE, &[U_COUNTS, decoration::BINDING, 2]),
+            (op::DECORATE, &[PUSH, decoration::BLOCK]),
+            (op::MEMBER_DECORATE, &[PUSH, 0, decoration::OFFSET, 0]),
+            (op::MEMBER_DECORATE, &[PUSH, 1, decoration::OFFSET, 4]),
+            (op::TYPE_INT, &[UINT, 32, 0]),
+            (op::TYPE_RUNTIME_ARRAY, &[RUNTIME_ARRAY, UINT]),
+            (op::TYPE_STRUCT, &[BUFFER, RUNTIME_ARRAY]),
+            (op::TYPE_STRUCT, &[PUSH, UINT, UINT]),
+            (op::TYPE_POINTER, &[BUFFER_PTR, storage_class::STORAGE_BUFFER, BUFFER]),
+            (op::TYPE_POINTER, &[PUSH_PTR, storage_class::PUSH_CONSTANT, PUSH]),
+            (op::VARIABLE, &[BUFFER_PTR, U_COUNTS, storage_class::STORAGE_BUFFER]),
+            (op::VARIABLE, &[PUSH_PTR, PUSHED, storage_class::PUSH_CONSTANT]),
+        ] {
+            words.extend(instruction(opcode, operands));
+        }
+        words
+    }
+
+    #[test]
+    fn reflects_compute_interface() {
+        let comp = reflect(&compute_module()).unwrap();
+        assert_eq!(comp.stage, vk::ShaderStageFlags::COMPUTE);
+        assert_eq!(comp.local_size, Some([64, 2, 1]));
+        assert_eq!(comp.push_constants, Some((0, 8)));
+        assert_eq!(comp.bindings, [DescriptorBinding {
+            set: 0,
+            binding: 2,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            count: 1,
+        }]);
+
+        let reflection = PipelineReflection::new(&[comp]).unwrap();
+        assert_eq!(reflection.local_size, Some([64, 2, 1]));
+        assert_eq!(reflection.push_constant_ranges[0].stage_flags, vk::ShaderStageFlags::COMPUTE);
+    }
+
+    #[test]
+    fn reflects_vertex_interface() {
+        let vert = reflect(&vertex_module()).unwrap();
+        assert_eq!(vert.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(vert.bindings, [DescriptorBinding {
+            set: 0,
+            binding: 0,
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            count: 1,
+        }]);
+        // from the vec4 at 16 to the end of the vec3 at 32
+        assert_eq!(vert.push_constants, Some((16, 28)));
+        assert_eq!(vert.local_size, None);
+
+        let locations: Vec<_> = vert.vertex_inputs.iter().map(|input| (input.location, input.components)).collect();
+        assert_eq!(locations, [(0, 3), (2, 2), (3, 4), (4, 4), (5, 4), (6, 4)]);
+    }
+
+    #[test]
+    fn merges_stages_and_checks_layouts() {
+        let vert = reflect(&vertex_module()).unwrap();
+        let frag = reflect(&fragment_module(1)).unwrap();
+        let reflection = PipelineReflection::new(&[vert.clone(), frag]).unwrap();
+
+        assert_eq!(reflection.sets.len(), 1);
+        let bindings: Vec<_> = reflection.sets[0]
+            .iter()
+            .map(|binding| (binding.binding, binding.descriptor_type, binding.stage_flags))
+            .collect();
+        assert_eq!(bindings, [
+            (0, vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX),
+            (1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT),
+        ]);
+
+        let mut dynamic = reflection.sets[0].clone();
+        dynamic[0].descriptor_type = vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC;
+        assert!(reflection.check_set_layouts(&[&dynamic]).is_ok());
+        assert!(reflection.check_set_layouts(&[&dynamic[..1]]).is_err());
+        assert!(reflection.check_set_layouts(&[]).is_err());
+
+        // the same binding declared differently by two stages
+        let clashing = reflect(&fragment_module(0)).unwrap();
+        assert!(PipelineReflection::new(&[vert, clashing]).is_err());
+    }
+
+    #[test]
+    fn checks_vertex_layout() {
+        let reflection = PipelineReflection::new(&[reflect(&vertex_module()).unwrap()]).unwrap();
+        let attribute = |location, format| vk::VertexInputAttributeDescription {
+            location,
+            format,
+            ..Default::default()
+        };
+
+        let mut layout = VertexLayout {
+            bindings: Vec::new(),
+            attributes: vec![
+                attribute(0, vk::Format::R32G32B32_SFLOAT),
+                // color at location 1 is not read by the shader
+                attribute(1, vk::Format::R8G8B8A8_UNORM),
+                attribute(2, vk::Format::R32G32_SFLOAT),
+            ],
+        };
+        layout.attributes.extend((3..7).map(|location| attribute(location, vk::Format::R32G32B32A32_SFLOAT)));
+        assert!(reflection.check_vertex_layout(&layout).is_ok());
+
+        layout.attributes[2].format = vk::Format::R32G32_UINT;
+        assert!(reflection.check_vertex_layout(&layout).is_err());
+
+        layout.attributes.remove(2);
+        assert!(reflection.check_vertex_layout(&layout).is_err());
+    }
+}
@@ -1,17 +1,21 @@
 use ash::vk;
 
+use super::{resource::{DeletionQueue, RenderPass}, RendererError};
+
 pub fn create_render_pass(
     device: &ash::Device,
+    deletion_queue: &DeletionQueue,
     color_format: vk::Format,
     depth_format: vk::Format,
-) -> vk::RenderPass {
+    color_final_layout: vk::ImageLayout,
+) -> Result<RenderPass, RendererError> {
     let color_attachment_desc = vk::AttachmentDescription::builder()
         .format(color_format)
         .samples(vk::SampleCountFlags::TYPE_1)
         .load_op(vk::AttachmentLoadOp::CLEAR)
         .store_op(vk::AttachmentStoreOp::STORE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+        .final_layout(color_final_layout)
         .build();
 
     let depth_attachment_desc = vk::AttachmentDescription::builder()
@@ -88,7 +92,6 @@ pub fn create_render_pass(
         .dependencies(&[dep_ext_0, dep_0_ext])
         .build();
 
-    unsafe {
-        device.create_render_pass(&info, None).unwrap()
-    }
+    let render_pass = unsafe { device.create_render_pass(&info, None)? };
+    Ok(RenderPass::new(render_pass, deletion_queue))
 }
@@ -0,0 +1,232 @@
+use std::{cell::RefCell, rc::Rc};
+
+use ash::vk;
+
+use super::{allocator::{Allocation, Allocator}, FRAMES_IN_FLIGHT};
+
+// owning wrappers around vulkan handles,
+// dropping one hands its handle to the deletion queue instead of destroying it right away
+// as a frame still in flight may be using it
+
+enum Garbage {
+    Buffer(vk::Buffer, Allocation),
+    Image(vk::Image, Allocation),
+    ImageView(vk::ImageView),
+    Sampler(vk::Sampler),
+    Framebuffer(vk::Framebuffer),
+    RenderPass(vk::RenderPass),
+    Pipeline(vk::Pipeline),
+    PipelineLayout(vk::PipelineLayout),
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+    DescriptorPool(vk::DescriptorPool),
+    CommandPool(vk::CommandPool),
+    Semaphore(vk::Semaphore),
+    Fence(vk::Fence),
+}
+
+impl Garbage {
+    unsafe fn destroy(self, device: &ash::Device, allocator: &mut Allocator) {
+        match self {
+            Self::Buffer(buffer, allocation) => {
+                device.destroy_buffer(buffer, None);
+                allocator.free(device, &allocation);
+            }
+            Self::Image(image, allocation) => {
+                device.destroy_image(image, None);
+                allocator.free(device, &allocation);
+            }
+            Self::ImageView(image_view) => device.destroy_image_view(image_view, None),
+            Self::Sampler(sampler) => device.destroy_sampler(sampler, None),
+            Self::Framebuffer(framebuffer) => device.destroy_framebuffer(framebuffer, None),
+            Self::RenderPass(render_pass) => device.destroy_render_pass(render_pass, None),
+            Self::Pipeline(pipeline) => device.destroy_pipeline(pipeline, None),
+            Self::PipelineLayout(pipeline_layout) => device.destroy_pipeline_layout(pipeline_layout, None),
+            Self::DescriptorSetLayout(layout) => device.destroy_descriptor_set_layout(layout, None),
+            // frees the descriptor sets allocated from it as well
+            Self::DescriptorPool(pool) => device.destroy_descriptor_pool(pool, None),
+            // frees the command buffers allocated from it as well
+            Self::CommandPool(pool) => device.destroy_command_pool(pool, None),
+            Self::Semaphore(semaphore) => device.destroy_semaphore(semaphore, None),
+            Self::Fence(fence) => device.destroy_fence(fence, None),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Frames {
+    current: usize,
+    garbage: [Vec<Garbage>; FRAMES_IN_FLIGHT as usize],
+}
+
+// shared by every owning handle, cloning it is cheap
+#[derive(Clone, Default)]
+pub struct DeletionQueue(Rc<RefCell<Frames>>);
+
+impl DeletionQueue {
+    fn push(&self, garbage: Garbage) {
+        let mut frames = self.0.borrow_mut();
+        let current = frames.current;
+        frames.garbage[current].push(garbage);
+    }
+
+    // must be called once the in flight fence of `frame` has signaled,
+    // destroys what was dropped the last time `frame` was recorded
+    // and queues everything dropped from now on behind it
+    pub unsafe fn begin_frame(&self, device: &ash::Device, allocator: &mut Allocator, frame: usize) {
+        let garbage = {
+            let mut frames = self.0.borrow_mut();
+            frames.current = frame;
+            std::mem::take(&mut frames.garbage[frame])
+        };
+        for garbage in garbage {
+            garbage.destroy(device, allocator);
+        }
+    }
+
+    // the device must be idle
+    pub unsafe fn flush(&self, device: &ash::Device, allocator: &mut Allocator) {
+        let garbage = std::mem::take(&mut self.0.borrow_mut().garbage);
+        for garbage in garbage.into_iter().flatten() {
+            garbage.destroy(device, allocator);
+        }
+    }
+}
+
+pub struct Buffer {
+    handle: vk::Buffer,
+    // only None while being dropped
+    allocation: Option<Allocation>,
+    deletion_queue: DeletionQueue,
+}
+
+impl Buffer {
+    pub fn new(handle: vk::Buffer, allocation: Allocation, deletion_queue: &DeletionQueue) -> Self {
+        Self {
+            handle,
+            allocation: Some(allocation),
+            deletion_queue: deletion_queue.clone(),
+        }
+    }
+
+    pub fn handle(&self) -> vk::Buffer {
+        self.handle
+    }
+
+    pub fn allocation(&self) -> &Allocation {
+        self.allocation.as_ref().unwrap()
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if let Some(allocation) = self.allocation.take() {
+            self.deletion_queue.push(Garbage::Buffer(self.handle, allocation));
+        }
+    }
+}
+
+pub struct Image {
+    handle: vk::Image,
+    // only None while being dropped
+    allocation: Option<Allocation>,
+    deletion_queue: DeletionQueue,
+}
+
+impl Image {
+    pub fn new(handle: vk::Image, allocation: Allocation, deletion_queue: &DeletionQueue) -> Self {
+        Self {
+            handle,
+            allocation: Some(allocation),
+            deletion_queue: deletion_queue.clone(),
+        }
+    }
+
+    pub fn handle(&self) -> vk::Image {
+        self.handle
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        if let Some(allocation) = self.allocation.take() {
+            self.deletion_queue.push(Garbage::Image(self.handle, allocation));
+        }
+    }
+}
+
+// remembers its bindings so pipelines can be checked against their shaders
+pub struct DescriptorSetLayout {
+    handle: vk::DescriptorSetLayout,
+    bindings: Vec<vk::DescriptorSetLayoutBinding>,
+    deletion_queue: DeletionQueue,
+}
+
+impl DescriptorSetLayout {
+    // `bindings` are the ones `handle` was created with, immutable samplers are not kept
+    pub fn new(handle: vk::DescriptorSetLayout, bindings: &[vk::DescriptorSetLayoutBinding], deletion_queue: &DeletionQueue) -> Self {
+        Self {
+            handle,
+            bindings: bindings
+                .iter()
+                .map(|binding| vk::DescriptorSetLayoutBinding {
+                    p_immutable_samplers: std::ptr::null(),
+                    ..*binding
+                })
+                .collect(),
+            deletion_queue: deletion_queue.clone(),
+        }
+    }
+
+    pub fn handle(&self) -> vk::DescriptorSetLayout {
+        self.handle
+    }
+
+    pub fn bindings(&self) -> &[vk::DescriptorSetLayoutBinding] {
+        &self.bindings
+    }
+}
+
+impl Drop for DescriptorSetLayout {
+    fn drop(&mut self) {
+        self.deletion_queue.push(Garbage::DescriptorSetLayout(self.handle));
+    }
+}
+
+macro_rules! owned_handle {
+    ($name:ident, $handle:ty) => {
+        pub struct $name {
+            handle: $handle,
+            deletion_queue: DeletionQueue,
+        }
+
+        impl $name {
+            pub fn new(handle: $handle, deletion_queue: &DeletionQueue) -> Self {
+                Self {
+                    handle,
+                    deletion_queue: deletion_queue.clone(),
+                }
+            }
+
+            pub fn handle(&self) -> $handle {
+                self.handle
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                self.deletion_queue.push(Garbage::$name(self.handle));
+            }
+        }
+    };
+}
+
+owned_handle!(ImageView, vk::ImageView);
+owned_handle!(Sampler, vk::Sampler);
+owned_handle!(Framebuffer, vk::Framebuffer);
+owned_handle!(RenderPass, vk::RenderPass);
+owned_handle!(Pipeline, vk::Pipeline);
+owned_handle!(PipelineLayout, vk::PipelineLayout);
+owned_handle!(DescriptorPool, vk::DescriptorPool);
+owned_handle!(CommandPool, vk::CommandPool);
+owned_handle!(Semaphore, vk::Semaphore);
+owned_handle!(Fence, vk::Fence);
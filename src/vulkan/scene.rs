@@ -0,0 +1,87 @@
+use crate::math::Affine3;
+
+use super::{mesh::MeshId, texture::TextureId};
+
+// everything drawn each frame, the transform is passed to the vertex shader
+// as an instance with indirect draws or as a push constant without
+#[derive(Clone, Copy, Debug)]
+pub struct Object {
+    pub mesh: MeshId,
+    pub texture: TextureId,
+    // model to world space
+    pub transform: Affine3,
+}
+
+// stays valid until the object is removed, after which it may be reused
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectId(usize);
+
+#[derive(Default)]
+pub struct Scene {
+    objects: Vec<Option<Object>>,
+    free_slots: Vec<usize>,
+}
+
+impl Scene {
+    pub fn add(&mut self, object: Object) -> ObjectId {
+        match self.free_slots.pop() {
+            Some(slot) => {
+                self.objects[slot] = Some(object);
+                ObjectId(slot)
+            }
+            None => {
+                self.objects.push(Some(object));
+                ObjectId(self.objects.len() - 1)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: ObjectId) -> Option<Object> {
+        let object = self.objects.get_mut(id.0)?.take()?;
+        self.free_slots.push(id.0);
+        Some(object)
+    }
+
+    pub fn get(&self, id: ObjectId) -> Option<&Object> {
+        self.objects.get(id.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: ObjectId) -> Option<&mut Object> {
+        self.objects.get_mut(id.0)?.as_mut()
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.objects.iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(mesh: usize) -> Object {
+        Object {
+            mesh: MeshId(mesh),
+            texture: TextureId::DEFAULT,
+            transform: Affine3::IDENTITY,
+        }
+    }
+
+    #[test]
+    fn removed_slots_are_reused() {
+        let mut scene = Scene::default();
+        let a = scene.add(object(0));
+        let b = scene.add(object(1));
+
+        assert_eq!(scene.remove(a).map(|object| object.mesh), Some(MeshId(0)));
+        assert!(scene.remove(a).is_none());
+        assert!(scene.get(a).is_none());
+        assert_eq!(scene.objects().count(), 1);
+
+        let c = scene.add(object(2));
+        assert_eq!(c, a);
+        assert_eq!(scene.get(c).map(|object| object.mesh), Some(MeshId(2)));
+        assert_eq!(scene.get(b).map(|object| object.mesh), Some(MeshId(1)));
+        assert_eq!(scene.objects().count(), 2);
+    }
+}
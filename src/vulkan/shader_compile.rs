@@ -0,0 +1,133 @@
+// shaderc setup shared by build.rs and hot reloading, included by build.rs with #[path]
+// so only std and shaderc can be used here
+use std::{
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+    path::{Component, Path, PathBuf},
+};
+
+// the same as shaders::Defines, which build.rs cannot reach
+pub type Defines = BTreeMap<String, Option<String>>;
+
+pub fn shaders_dir() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders"))
+}
+
+// files with other extensions are only compiled when included
+pub fn shader_kind(file_name: &str) -> Option<shaderc::ShaderKind> {
+    match Path::new(file_name).extension()?.to_str()? {
+        "vert" => Some(shaderc::ShaderKind::Vertex),
+        "frag" => Some(shaderc::ShaderKind::Fragment),
+        "comp" => Some(shaderc::ShaderKind::Compute),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompileSettings {
+    pub optimization: shaderc::OptimizationLevel,
+    pub debug_info: bool,
+}
+
+// part of the key of compiled permutations, shaderc::OptimizationLevel does not implement Hash
+impl Hash for CompileSettings {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.optimization as u32).hash(state);
+        self.debug_info.hash(state);
+    }
+}
+
+// `file_name` is relative to the shaders directory,
+// errors are returned as diagnostics naming the file and line
+pub fn compile(
+    compiler: &shaderc::Compiler,
+    file_name: &str,
+    defines: &Defines,
+    settings: CompileSettings,
+) -> Result<shaderc::CompilationArtifact, String> {
+    let kind = shader_kind(file_name).ok_or_else(|| format!("{}: unknown shader stage", file_name))?;
+    let source = read_source(file_name)?;
+
+    let mut options = shaderc::CompileOptions::new().ok_or("could not initialize shaderc compile options")?;
+    options.set_include_callback(resolve_include);
+    for (name, value) in defines {
+        options.add_macro_definition(name, value.as_deref());
+    }
+    options.set_optimization_level(settings.optimization);
+    if settings.debug_info {
+        options.set_generate_debug_info();
+    }
+
+    compiler
+        .compile_into_spirv(&source, kind, file_name, "main", Some(&options))
+        .map_err(|err| err.to_string())
+}
+
+fn read_source(file_name: &str) -> Result<String, String> {
+    std::fs::read_to_string(shaders_dir().join(file_name)).map_err(|err| format!("{}: {}", file_name, err))
+}
+
+// #include "file" is relative to the including file, #include <file> to the shaders directory,
+// neither may leave the shaders directory
+fn resolve_include(
+    requested: &str,
+    include_type: shaderc::IncludeType,
+    requesting: &str,
+    _depth: usize,
+) -> shaderc::IncludeCallbackResult {
+    let base = match include_type {
+        shaderc::IncludeType::Relative => Path::new(requesting).parent().unwrap_or(Path::new("")),
+        shaderc::IncludeType::Standard => Path::new(""),
+    };
+    let resolved_name = normalize(&base.join(requested))
+        .ok_or_else(|| format!("{} is outside of the shaders directory", requested))?
+        .to_string_lossy()
+        .replace('\\', "/");
+
+    Ok(shaderc::ResolvedInclude {
+        content: read_source(&resolved_name)?,
+        resolved_name,
+    })
+}
+
+// resolves . and .. without touching the file system, None if the path leaves its root
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_stay_in_shaders_dir() {
+        assert_eq!(normalize(Path::new("lib/../common.glsl")), Some(PathBuf::from("common.glsl")));
+        assert_eq!(normalize(Path::new("./lib/./affine.glsl")), Some(PathBuf::from("lib/affine.glsl")));
+        assert_eq!(normalize(Path::new("../secret.glsl")), None);
+        assert_eq!(normalize(Path::new("lib/../../secret.glsl")), None);
+        assert_eq!(normalize(Path::new("/etc/passwd")), None);
+    }
+
+    #[test]
+    fn resolves_includes_from_the_shaders_dir() {
+        let include = resolve_include("affine.glsl", shaderc::IncludeType::Relative, "main.vert", 1).unwrap();
+        assert_eq!(include.resolved_name, "affine.glsl");
+        assert_eq!(include.content, read_source("affine.glsl").unwrap());
+
+        assert!(resolve_include("../../Cargo.toml", shaderc::IncludeType::Relative, "main.vert", 1).is_err());
+        assert!(resolve_include("missing.glsl", shaderc::IncludeType::Standard, "main.vert", 1).is_err());
+    }
+}
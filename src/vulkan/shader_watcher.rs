@@ -0,0 +1,67 @@
+use std::{path::{Path, PathBuf}, time::SystemTime};
+
+// polls the modification times of shader sources, cheap enough to do once per frame
+pub struct ShaderWatcher {
+    // None while the file is missing, e.g. while an editor replaces it
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ShaderWatcher {
+    pub fn new<P: Into<PathBuf>>(paths: impl IntoIterator<Item = P>) -> Self {
+        Self {
+            files: paths
+                .into_iter()
+                .map(|path| {
+                    let path = path.into();
+                    let modified = modified(&path);
+                    (path, modified)
+                })
+                .collect(),
+        }
+    }
+
+    // whether any file changed since the last poll,
+    // a file that disappeared only counts once it is back
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last_modified) in &mut self.files {
+            let Some(modified) = modified(path) else {
+                continue;
+            };
+            if *last_modified != Some(modified) {
+                log::info!("{} changed", path.display());
+                *last_modified = Some(modified);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn poll_reports_each_change_once() {
+        let path = std::env::temp_dir().join(format!("ash_learn_watch_{}.frag", std::process::id()));
+        std::fs::write(&path, "#version 450").unwrap();
+
+        let mut watcher = ShaderWatcher::new([&path]);
+        assert!(!watcher.poll());
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!watcher.poll());
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::{shader_compile::CompileSettings, RendererError};
+
+// macro name to optional value, sorted so that equal sets compare and hash equal
+pub type Defines = BTreeMap<String, Option<String>>;
+
+// SPIR-V of a shader in src/shaders, compiled by build.rs
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedShader {
+    pub file_name: &'static str,
+    // the first one is compiled without defines
+    pub permutations: &'static [Permutation],
+}
+
+#[derive(Debug)]
+pub struct Permutation {
+    // sorted by name
+    pub defines: &'static [(&'static str, Option<&'static str>)],
+    pub spirv: &'static [u32],
+}
+
+// one constant per shader named after its file, e.g. MAIN_VERT for main.vert
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
+
+impl EmbeddedShader {
+    pub fn spirv(&self, defines: &Defines) -> Result<&'static [u32], RendererError> {
+        self.permutations
+            .iter()
+            .find(|permutation| {
+                permutation.defines.len() == defines.len()
+                    && permutation.defines
+                        .iter()
+                        .zip(defines)
+                        .all(|(&(name, value), (defined_name, defined_value))| {
+                            name == defined_name && value == defined_value.as_deref()
+                        })
+            })
+            .map(|permutation| permutation.spirv)
+            .ok_or_else(|| RendererError::MissingShaderPermutation {
+                file_name: self.file_name,
+                defines: defines.clone(),
+            })
+    }
+}
+
+// per pipeline compile settings, the shaders are compiled when building a pipeline with
+// either of them set, otherwise the cargo profile's are used, the same as for the embedded SPIR-V
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShaderOptions {
+    pub optimization: Option<shaderc::OptimizationLevel>,
+    pub debug_info: Option<bool>,
+}
+
+impl ShaderOptions {
+    fn is_default(&self) -> bool {
+        self.optimization.is_none() && self.debug_info.is_none()
+    }
+}
+
+// compiles the define sets and options the embedded SPIR-V has no permutation for,
+// and the current sources for hot reloading,
+// a permutation is compiled once per define set and settings until the sources change
+pub struct ShaderCompiler {
+    // created on the first compile, as most pipelines only use the embedded SPIR-V
+    compiler: Option<shaderc::Compiler>,
+    profile_settings: CompileSettings,
+    cache: HashMap<(&'static str, Defines, CompileSettings), Vec<u32>>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Self {
+        // build.rs follows the cargo profile the same way
+        let profile_settings = if cfg!(debug_assertions) {
+            CompileSettings {
+                optimization: shaderc::OptimizationLevel::Zero,
+                debug_info: true,
+            }
+        } else {
+            CompileSettings {
+                optimization: shaderc::OptimizationLevel::Performance,
+                debug_info: false,
+            }
+        };
+
+        Self {
+            compiler: None,
+            profile_settings,
+            cache: HashMap::new(),
+        }
+    }
+
+    // the embedded SPIR-V when it has the permutation and the options are the profile's,
+    // compiled from the current sources otherwise
+    pub fn spirv(&mut self, shader: &EmbeddedShader, defines: &Defines, options: ShaderOptions) -> Result<&[u32], RendererError> {
+        if options.is_default() {
+            if let Ok(spirv) = shader.spirv(defines) {
+                return Ok(spirv);
+            }
+        }
+        self.compile(shader, defines, options)
+    }
+
+    pub fn compile(&mut self, shader: &EmbeddedShader, defines: &Defines, options: ShaderOptions) -> Result<&[u32], RendererError> {
+        let settings = CompileSettings {
+            optimization: options.optimization.unwrap_or(self.profile_settings.optimization),
+            debug_info: options.debug_info.unwrap_or(self.profile_settings.debug_info),
+        };
+        let key = (shader.file_name, defines.clone(), settings);
+        if !self.cache.contains_key(&key) {
+            let compiler = match &mut self.compiler {
+                Some(compiler) => compiler,
+                None => self.compiler.insert(shaderc::Compiler::new().ok_or_else(|| RendererError::ShaderCompile {
+                    path: String::new(),
+                    diagnostics: "could not initialize shaderc".to_owned(),
+                })?),
+            };
+            let artifact = super::shader_compile::compile(compiler, shader.file_name, defines, settings)
+                .map_err(|diagnostics| RendererError::ShaderCompile {
+                    path: shader.file_name.to_owned(),
+                    diagnostics,
+                })?;
+            self.cache.insert(key.clone(), artifact.as_binary().to_vec());
+        }
+        Ok(&self.cache[&key])
+    }
+
+    // to be called once any source, including included files, changed
+    #[cfg(feature = "hot-reload")]
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // GraphicsPipelineBuilder::define selects the permutation of both stages
+    #[test]
+    fn main_shaders_have_the_instanced_permutation() {
+        let defines = Defines::from([("INSTANCED".to_owned(), None)]);
+        assert!(MAIN_VERT.spirv(&defines).is_ok());
+        assert!(MAIN_FRAG.spirv(&defines).is_ok());
+    }
+
+    #[test]
+    fn uses_the_embedded_permutation_without_options() {
+        let mut shader_compiler = ShaderCompiler::new();
+        let defines = Defines::from([("INSTANCED".to_owned(), None)]);
+        let spirv = shader_compiler.spirv(&MAIN_VERT, &defines, ShaderOptions::default()).unwrap();
+        assert_eq!(spirv.as_ptr(), MAIN_VERT.spirv(&defines).unwrap().as_ptr());
+        assert!(shader_compiler.compiler.is_none());
+    }
+
+    #[test]
+    fn compiles_undeclared_permutations_and_options() {
+        let mut shader_compiler = ShaderCompiler::new();
+        let defines = Defines::from([("UNDECLARED".to_owned(), Some("1".to_owned()))]);
+        let with_debug_info = ShaderOptions {
+            optimization: Some(shaderc::OptimizationLevel::Zero),
+            debug_info: Some(true),
+        };
+        let without_debug_info = ShaderOptions {
+            debug_info: Some(false),
+            ..with_debug_info
+        };
+
+        shader_compiler.spirv(&MAIN_FRAG, &defines, ShaderOptions::default()).unwrap();
+        let debug_len = shader_compiler.spirv(&MAIN_FRAG, &Defines::new(), with_debug_info).unwrap().len();
+        let len = shader_compiler.spirv(&MAIN_FRAG, &Defines::new(), without_debug_info).unwrap().len();
+        assert!(len < debug_len);
+        // a second time from the cache
+        shader_compiler.spirv(&MAIN_FRAG, &defines, ShaderOptions::default()).unwrap();
+        assert_eq!(shader_compiler.cache.len(), 3);
+    }
+}
@@ -3,6 +3,37 @@ use ash::{
     vk,
 };
 
+use super::{resource::{DeletionQueue, Framebuffer, ImageView}, RendererError};
+
+pub struct SwapchainTarget {
+    pub surface: Surface,
+    pub surface_khr: vk::SurfaceKHR,
+    pub surface_format: vk::SurfaceFormatKHR,
+
+    pub present_queue: vk::Queue,
+    pub present_family_index: u32,
+
+    pub swapchain: Swapchain,
+    pub swapchain_khr: vk::SwapchainKHR,
+    pub present_mode: vk::PresentModeKHR,
+    pub images: Vec<vk::Image>,
+    pub image_views: Vec<ImageView>,
+    pub framebuffers: Vec<Framebuffer>,
+}
+
+// dropped once the device is idle, see Vulkan::drop, or before anything was presented,
+// the image views and framebuffers are released through the deletion queue
+impl Drop for SwapchainTarget {
+    fn drop(&mut self) {
+        self.framebuffers.clear();
+        self.image_views.clear();
+        unsafe {
+            self.swapchain.destroy_swapchain(self.swapchain_khr, None);
+            self.surface.destroy_surface(self.surface_khr, None);
+        }
+    }
+}
+
 // we need multiple color images for the spwachian
 // as we can have one image rendered to and one which is being presented
 pub fn create_swapchain_khr(
@@ -12,76 +43,106 @@ pub fn create_swapchain_khr(
     surface_format: vk::SurfaceFormatKHR,
     present_mode: vk::PresentModeKHR,
     device: &ash::Device,
+    deletion_queue: &DeletionQueue,
     swapchain: &Swapchain,
     physical_device: vk::PhysicalDevice,
     graphics_family_index: u32,
     present_family_index: u32,
-) -> (
+    old_swapchain_khr: vk::SwapchainKHR,
+) -> Result<(
     vk::SwapchainKHR,
     Vec<vk::Image>,
-    Vec<vk::ImageView>,
-) {
-    let capabilities = unsafe{surface.get_physical_device_surface_capabilities(physical_device, surface_khr).unwrap()};
+    Vec<ImageView>,
+), RendererError> {
+    let capabilities = unsafe{surface.get_physical_device_surface_capabilities(physical_device, surface_khr)?};
 
+    // u32::MAX means the surface size is determined by the swapchain extent
+    if capabilities.current_extent.width != u32::MAX {
+        *swapchain_extent = capabilities.current_extent;
+    }
     swapchain_extent.width = swapchain_extent.width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width);
     swapchain_extent.height = swapchain_extent.height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height);
 
+    // a max_image_count of 0 means there is no limit
+    let mut min_image_count = capabilities.min_image_count + 1;
+    if capabilities.max_image_count > 0 {
+        min_image_count = min_image_count.min(capabilities.max_image_count);
+    }
+
+    // concurrent sharing requires distinct queue families
+    let queue_family_indices = [graphics_family_index, present_family_index];
+    let queue_family_indices: &[u32] = if graphics_family_index != present_family_index {
+        &queue_family_indices
+    } else {
+        &[]
+    };
+    let image_sharing_mode = if queue_family_indices.is_empty() {
+        vk::SharingMode::EXCLUSIVE
+    } else {
+        vk::SharingMode::CONCURRENT
+    };
+
     let create_info = vk::SwapchainCreateInfoKHR::builder()
         .surface(surface_khr)
-        .min_image_count((capabilities.min_image_count + 1).min(capabilities.max_image_count))
+        .min_image_count(min_image_count)
         .image_format(surface_format.format)
         .image_color_space(surface_format.color_space)
         .image_extent(*swapchain_extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        // TRANSFER_SRC allows copying presented images out for screenshots
+        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
         .pre_transform(capabilities.current_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
         .present_mode(present_mode)
         .clipped(true)
-        .image_sharing_mode(vk::SharingMode::CONCURRENT)
-        .queue_family_indices(&[graphics_family_index, present_family_index])
+        .old_swapchain(old_swapchain_khr)
+        .image_sharing_mode(image_sharing_mode)
+        .queue_family_indices(queue_family_indices)
         .build();
 
-    let swapchain_khr = unsafe { swapchain.create_swapchain(&create_info, None).unwrap() };
-    let swapchain_images = unsafe { swapchain.get_swapchain_images(swapchain_khr).unwrap() };
+    let swapchain_khr = unsafe { swapchain.create_swapchain(&create_info, None)? };
+    let swapchain_images = unsafe { swapchain.get_swapchain_images(swapchain_khr)? };
     let swapchain_image_views = swapchain_images
         .iter()
         .map(|&image| {
             super::img::create_image_view(
                 device, 
+                deletion_queue,
                 image, 
                 surface_format.format, 
                 vk::ImageAspectFlags::COLOR
             )
         })
-        .collect();
+        .collect::<Result<_, _>>()?;
 
-    (
+    Ok((
         swapchain_khr,
         swapchain_images,
         swapchain_image_views,
-    )
+    ))
 }
 
 pub fn create_swapchain_framebuffers(
     device: &ash::Device,
-    image_views: &[vk::ImageView],
+    deletion_queue: &DeletionQueue,
+    image_views: &[ImageView],
     depth_image_view: vk::ImageView,
     render_pass: vk::RenderPass,
     extent: vk::Extent2D,
-) -> Vec<vk::Framebuffer> {
+) -> Result<Vec<Framebuffer>, RendererError> {
     image_views
         .iter()
-        .map(|&image_view| {
+        .map(|image_view| {
             let info = vk::FramebufferCreateInfo::builder()
-                .attachments(&[image_view, depth_image_view])
+                .attachments(&[image_view.handle(), depth_image_view])
                 .render_pass(render_pass)
                 .width(extent.width)
                 .height(extent.height)
                 .layers(1)
                 .build();
 
-            unsafe { device.create_framebuffer(&info, None).unwrap() }
+            let framebuffer = unsafe { device.create_framebuffer(&info, None)? };
+            Ok(Framebuffer::new(framebuffer, deletion_queue))
         })
         .collect()
 }
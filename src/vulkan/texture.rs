@@ -0,0 +1,286 @@
+use ash::vk;
+
+use image::RgbaImage;
+
+use super::{
+    allocator::Allocator,
+    barrier::{self, Access},
+    command,
+    img,
+    resource::{DeletionQueue, Image, ImageView},
+    upload::{self, ImageUpload, UploadQueues},
+    RendererError,
+};
+
+// returned by Vulkan::upload_texture
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(pub(super) usize);
+
+impl TextureId {
+    // assets/apple.png, uploaded by Vulkan::new
+    pub const DEFAULT: Self = Self(0);
+}
+
+// always viewed as an array, a single image is an array of one layer
+pub struct Texture {
+    pub image: Image,
+    pub image_view: ImageView,
+    // the camera buffer and this texture, bound for every object using it
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+pub const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+// how the mip levels after the first are made, chosen by Vulkan::new from the support for FORMAT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipGeneration {
+    // blitted from one level to the next with linear filtering on the graphics queue
+    Blit,
+    // downsampled on the cpu and uploaded with the first level
+    Cpu,
+}
+
+// down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).leading_zeros()
+}
+
+// the next mip level, each side halved and rounded down but at least 1,
+// averaged in linear space as linear blits of sRGB images are
+pub fn downsample(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let mut sum = [0.0; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = image.get_pixel((x * 2 + dx).min(width - 1), (y * 2 + dy).min(height - 1));
+            for channel in 0..3 {
+                sum[channel] += srgb_to_linear(pixel[channel]);
+            }
+            sum[3] += pixel[3] as f32 / 255.0;
+        }
+        let [r, g, b, a] = sum.map(|channel| channel / 4.0);
+        image::Rgba([linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), (a * 255.0).round() as u8])
+    })
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// `layers` must all have the same size, the image gets a full mip chain
+pub fn create_texture_image(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    deletion_queue: &DeletionQueue,
+    queues: &UploadQueues,
+    layers: &[RgbaImage],
+    mip_generation: MipGeneration,
+) -> Result<(Image, ImageView), RendererError> {
+    let Some(first) = layers.first() else {
+        return Err(RendererError::InvalidTexture("no layers".to_owned()));
+    };
+    let (width, height) = first.dimensions();
+    if let Some(layer) = layers.iter().position(|layer| layer.dimensions() != (width, height)) {
+        return Err(RendererError::InvalidTexture(format!(
+            "layer {} is {:?} but layer 0 is {:?}",
+            layer, layers[layer].dimensions(), (width, height),
+        )));
+    }
+    let layer_count = layers.len() as u32;
+    let mip_levels = mip_level_count(width, height);
+
+    let image = img::create_image(
+        device,
+        allocator,
+        deletion_queue,
+        width,
+        height,
+        layer_count,
+        mip_levels,
+        // blits read the previous level
+        vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        FORMAT,
+        vk::ImageTiling::OPTIMAL,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+
+    let image_view = ImageView::new(unsafe { device.create_image_view(
+        &vk::ImageViewCreateInfo::builder()
+            .image(image.handle())
+            .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+            .format(FORMAT)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: mip_levels,
+                base_array_layer: 0,
+                layer_count,
+            }),
+        None,
+    )? }, deletion_queue);
+
+    let level_bytes = |layers: &[RgbaImage]| -> Vec<u8> {
+        layers.iter().flat_map(|layer| layer.as_raw().iter().copied()).collect()
+    };
+    let levels = match mip_generation {
+        MipGeneration::Blit => vec![level_bytes(layers)],
+        MipGeneration::Cpu => {
+            let mut levels = vec![level_bytes(layers)];
+            let mut level: Vec<_> = layers.to_vec();
+            for _ in 1..mip_levels {
+                level = level.iter().map(downsample).collect();
+                levels.push(level_bytes(&level));
+            }
+            levels
+        }
+    };
+
+    upload::upload(
+        device,
+        allocator,
+        deletion_queue,
+        queues,
+        &[],
+        &[ImageUpload {
+            image: image.handle(),
+            extent: vk::Extent2D {
+                width,
+                height,
+            },
+            layers: layer_count,
+            levels: levels.iter().map(Vec::as_slice).collect(),
+        }],
+    )?;
+
+    if mip_generation == MipGeneration::Blit && mip_levels > 1 {
+        generate_mips(device, queues, image.handle(), vk::Extent2D { width, height }, layer_count, mip_levels)?;
+    }
+
+    Ok((image, image_view))
+}
+
+// blits on the graphics queue as transfer queues can not, the first level is read
+// in SHADER_READ_ONLY_OPTIMAL as left by the upload and every level is left in it
+fn generate_mips(
+    device: &ash::Device,
+    queues: &UploadQueues,
+    image: vk::Image,
+    extent: vk::Extent2D,
+    layer_count: u32,
+    mip_levels: u32,
+) -> Result<(), RendererError> {
+    let levels = |base_mip_level, level_count| vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level,
+        level_count,
+        base_array_layer: 0,
+        layer_count,
+    };
+    let layers = |mip_level| vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level,
+        base_array_layer: 0,
+        layer_count,
+    };
+    let corner = |extent: vk::Extent2D| vk::Offset3D {
+        x: extent.width as i32,
+        y: extent.height as i32,
+        z: 1,
+    };
+
+    unsafe {
+        let command_buffer = command::begin_one_time_commands(device, queues.graphics_command_pool)?;
+
+        barrier::image_barrier(
+            device,
+            command_buffer,
+            image,
+            levels(0, 1),
+            (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            Access::FRAGMENT_SHADER_READ,
+            Access::TRANSFER_READ,
+        );
+        barrier::image_barrier(
+            device,
+            command_buffer,
+            image,
+            levels(1, mip_levels - 1),
+            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+            Access::NONE_BEFORE,
+            Access::TRANSFER_WRITE,
+        );
+
+        for level in 1..mip_levels {
+            device.cmd_blit_image(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[vk::ImageBlit {
+                    src_subresource: layers(level - 1),
+                    src_offsets: [vk::Offset3D::default(), corner(upload::level_extent(extent, level - 1))],
+                    dst_subresource: layers(level),
+                    dst_offsets: [vk::Offset3D::default(), corner(upload::level_extent(extent, level))],
+                }],
+                vk::Filter::LINEAR,
+            );
+            // the next level is blitted from this one
+            barrier::image_barrier(
+                device,
+                command_buffer,
+                image,
+                levels(level, 1),
+                (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+                Access::TRANSFER_WRITE,
+                Access::TRANSFER_READ,
+            );
+        }
+
+        barrier::image_barrier(
+            device,
+            command_buffer,
+            image,
+            levels(0, mip_levels),
+            (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            Access::TRANSFER_READ,
+            Access::FRAGMENT_SHADER_READ,
+        );
+
+        command::end_one_time_commands(device, queues.graphics_command_pool, queues.graphics_queue, command_buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chain_ends_at_one_texel() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 1), 9);
+        assert_eq!(mip_level_count(300, 200), 9);
+
+        // odd sides round down, the last column is left out like in a blit
+        let image = RgbaImage::from_fn(5, 2, |x, _| if x % 2 == 0 { image::Rgba([0, 0, 0, 0]) } else { image::Rgba([255; 4]) });
+        let level = downsample(&image);
+        assert_eq!(level.dimensions(), (2, 1));
+        // half the light in linear space is brighter than half the sRGB value
+        assert_eq!(level.get_pixel(0, 0).0, [188, 188, 188, 128]);
+        assert_eq!(downsample(&level).dimensions(), (1, 1));
+    }
+}
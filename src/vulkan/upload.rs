@@ -0,0 +1,256 @@
+use ash::vk;
+
+use super::{allocator::Allocator, buffer, command, resource::DeletionQueue, RendererError};
+
+// copies are recorded on the transfer queue,
+// which is the graphics queue when there is no dedicated transfer family
+#[derive(Clone, Copy)]
+pub struct UploadQueues {
+    pub transfer_queue: vk::Queue,
+    pub transfer_family_index: u32,
+    pub transfer_command_pool: vk::CommandPool,
+    pub graphics_queue: vk::Queue,
+    pub graphics_family_index: u32,
+    pub graphics_command_pool: vk::CommandPool,
+}
+
+// the whole buffer is written, starting at offset 0
+pub struct BufferUpload<'a> {
+    pub buffer: vk::Buffer,
+    pub bytes: &'a [u8],
+    // how the graphics queue reads it afterwards
+    pub dst_stage: vk::PipelineStageFlags,
+    pub dst_access: vk::AccessFlags,
+}
+
+// the first levels.len() mip levels of every layer of a color image are written
+// and left in SHADER_READ_ONLY_OPTIMAL for fragment shaders
+pub struct ImageUpload<'a> {
+    pub image: vk::Image,
+    // of the first level, halved for every following one
+    pub extent: vk::Extent2D,
+    pub layers: u32,
+    // from the largest, each with one layer after the other
+    pub levels: Vec<&'a [u8]>,
+}
+
+pub fn level_extent(extent: vk::Extent2D, level: u32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: (extent.width >> level).max(1),
+        height: (extent.height >> level).max(1),
+    }
+}
+
+// copy offsets must be multiples of the texel size, 16 covers every format
+const STAGING_ALIGNMENT: vk::DeviceSize = 16;
+
+// stages everything in one host visible buffer and waits for the copies to finish
+pub fn upload(
+    device: &ash::Device,
+    allocator: &mut Allocator,
+    deletion_queue: &DeletionQueue,
+    queues: &UploadQueues,
+    buffers: &[BufferUpload],
+    images: &[ImageUpload],
+) -> Result<(), RendererError> {
+    let buffers: Vec<_> = buffers.iter().filter(|upload| !upload.bytes.is_empty()).collect();
+    if buffers.is_empty() && images.is_empty() {
+        return Ok(());
+    }
+
+    let mut staging_size = 0;
+    let mut staging_offset = |bytes: &[u8]| {
+        let offset = staging_size;
+        staging_size = (offset + bytes.len() as vk::DeviceSize).next_multiple_of(STAGING_ALIGNMENT);
+        offset
+    };
+    let buffer_offsets: Vec<_> = buffers.iter().map(|upload| staging_offset(upload.bytes)).collect();
+    let image_offsets: Vec<Vec<_>> = images
+        .iter()
+        .map(|upload| upload.levels.iter().map(|bytes| staging_offset(bytes)).collect())
+        .collect();
+
+    unsafe {
+        let staging_buffer = buffer::create_buffer(
+            device,
+            allocator,
+            deletion_queue,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            staging_size,
+        )?;
+
+        {
+            let ptr = staging_buffer.allocation().mapped_ptr().unwrap();
+            let sources = buffers.iter().map(|upload| upload.bytes).chain(images.iter().flat_map(|upload| upload.levels.iter().copied()));
+            for (bytes, &offset) in sources.zip(buffer_offsets.iter().chain(image_offsets.iter().flatten())) {
+                ptr.add(offset as usize).copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+            }
+        }
+
+        let command_buffer = command::begin_one_time_commands(device, queues.transfer_command_pool)?;
+
+        for (upload, &offset) in buffers.iter().zip(&buffer_offsets) {
+            device.cmd_copy_buffer(
+                command_buffer,
+                staging_buffer.handle(),
+                upload.buffer,
+                &[vk::BufferCopy {
+                    src_offset: offset,
+                    dst_offset: 0,
+                    size: upload.bytes.len() as vk::DeviceSize,
+                }],
+            );
+        }
+
+        let subresource_range = |upload: &ImageUpload| vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: upload.levels.len() as u32,
+            base_array_layer: 0,
+            layer_count: upload.layers,
+        };
+
+        let to_transfer_dst: Vec<_> = images
+            .iter()
+            .map(|upload| {
+                vk::ImageMemoryBarrier::builder()
+                    .image(upload.image)
+                    .subresource_range(subresource_range(upload))
+                    .old_layout(vk::ImageLayout::UNDEFINED)
+                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .build()
+            })
+            .collect();
+        if !to_transfer_dst.is_empty() {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &to_transfer_dst,
+            );
+        }
+
+        for (upload, offsets) in images.iter().zip(&image_offsets) {
+            let copies: Vec<_> = offsets
+                .iter()
+                .enumerate()
+                .map(|(level, &offset)| {
+                    let extent = level_extent(upload.extent, level as u32);
+                    vk::BufferImageCopy::builder()
+                        .buffer_offset(offset)
+                        .image_subresource(vk::ImageSubresourceLayers {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            mip_level: level as u32,
+                            base_array_layer: 0,
+                            layer_count: upload.layers,
+                        })
+                        .image_extent(vk::Extent3D {
+                            width: extent.width,
+                            height: extent.height,
+                            depth: 1,
+                        })
+                        .build()
+                })
+                .collect();
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer.handle(),
+                upload.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &copies,
+            );
+        }
+
+        // with a dedicated transfer family the resources are released by the transfer queue
+        // and acquired by the graphics queue with a matching pair of barriers,
+        // otherwise a single barrier makes the writes visible to the graphics pipeline
+        let ownership_transfer = queues.transfer_family_index != queues.graphics_family_index;
+        let (src_family_index, dst_family_index) = if ownership_transfer {
+            (queues.transfer_family_index, queues.graphics_family_index)
+        } else {
+            (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+        };
+
+        let dst_stage = buffers
+            .iter()
+            .fold(vk::PipelineStageFlags::empty(), |stages, upload| stages | upload.dst_stage)
+            | if images.is_empty() { vk::PipelineStageFlags::empty() } else { vk::PipelineStageFlags::FRAGMENT_SHADER };
+
+        // a release only needs the source access and an acquire the destination access,
+        // the other one is ignored, a barrier without ownership transfer needs both
+        let upload_barriers = |release: bool, acquire: bool| {
+            let src_access = if release { vk::AccessFlags::TRANSFER_WRITE } else { vk::AccessFlags::empty() };
+            let dst_access = |access: vk::AccessFlags| if acquire { access } else { vk::AccessFlags::empty() };
+
+            let buffer_barriers: Vec<_> = buffers
+                .iter()
+                .map(|upload| {
+                    vk::BufferMemoryBarrier::builder()
+                        .buffer(upload.buffer)
+                        .offset(0)
+                        .size(vk::WHOLE_SIZE)
+                        .src_access_mask(src_access)
+                        .dst_access_mask(dst_access(upload.dst_access))
+                        .src_queue_family_index(src_family_index)
+                        .dst_queue_family_index(dst_family_index)
+                        .build()
+                })
+                .collect();
+            let image_barriers: Vec<_> = images
+                .iter()
+                .map(|upload| {
+                    vk::ImageMemoryBarrier::builder()
+                        .image(upload.image)
+                        .subresource_range(subresource_range(upload))
+                        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                        .src_access_mask(src_access)
+                        .dst_access_mask(dst_access(vk::AccessFlags::SHADER_READ))
+                        .src_queue_family_index(src_family_index)
+                        .dst_queue_family_index(dst_family_index)
+                        .build()
+                })
+                .collect();
+
+            (buffer_barriers, image_barriers)
+        };
+
+        let (buffer_barriers, image_barriers) = upload_barriers(true, !ownership_transfer);
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            if ownership_transfer { vk::PipelineStageFlags::BOTTOM_OF_PIPE } else { dst_stage },
+            vk::DependencyFlags::empty(),
+            &[],
+            &buffer_barriers,
+            &image_barriers,
+        );
+
+        command::end_one_time_commands(device, queues.transfer_command_pool, queues.transfer_queue, command_buffer)?;
+
+        if ownership_transfer {
+            let command_buffer = command::begin_one_time_commands(device, queues.graphics_command_pool)?;
+
+            let (buffer_barriers, image_barriers) = upload_barriers(false, true);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buffer_barriers,
+                &image_barriers,
+            );
+
+            command::end_one_time_commands(device, queues.graphics_command_pool, queues.graphics_queue, command_buffer)?;
+        }
+    }
+
+    Ok(())
+}
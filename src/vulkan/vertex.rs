@@ -0,0 +1,102 @@
+use std::mem;
+
+use ash::vk;
+
+pub use ash_learn_derive::VertexInput;
+
+// implemented with #[derive(VertexInput)], see ash_learn_derive for the supported field types and attributes
+pub trait VertexInput: Sized {
+    const INPUT_RATE: vk::VertexInputRate;
+
+    fn attribute_descriptions(binding: u32) -> Vec<vk::VertexInputAttributeDescription>;
+
+    fn binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: mem::size_of::<Self>() as u32,
+            input_rate: Self::INPUT_RATE,
+        }
+    }
+}
+
+// descriptions of one or more vertex input types, each bound to the next binding
+#[derive(Clone, Debug, Default)]
+pub struct VertexLayout {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexLayout {
+    pub fn with<V: VertexInput>(mut self) -> Self {
+        let binding = self.bindings.len() as u32;
+        self.bindings.push(V::binding_description(binding));
+        self.attributes.extend(V::attribute_descriptions(binding));
+        self
+    }
+}
+
+#[derive(VertexInput, Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    pub tex_coord: [f32; 2],
+    pub normal: [f32; 3],
+}
+
+pub type Index = u32;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(VertexInput)]
+    #[repr(C)]
+    #[instance]
+    struct Instance {
+        #[location = 4]
+        transform: [[f32; 4]; 4],
+        tint: [u8; 4],
+        #[format = "R8G8B8A8_UINT"]
+        flags: [u8; 4],
+    }
+
+    fn attributes<V: VertexInput>(binding: u32) -> Vec<(u32, u32, vk::Format, u32)> {
+        V::attribute_descriptions(binding)
+            .iter()
+            .map(|desc| (desc.location, desc.binding, desc.format, desc.offset))
+            .collect()
+    }
+
+    #[test]
+    fn vertex_attributes_follow_fields() {
+        assert_eq!(attributes::<Vertex>(0), [
+            (0, 0, vk::Format::R32G32B32_SFLOAT, 0),
+            (1, 0, vk::Format::R32G32B32_SFLOAT, 12),
+            (2, 0, vk::Format::R32G32_SFLOAT, 24),
+            (3, 0, vk::Format::R32G32B32_SFLOAT, 32),
+        ]);
+
+        let binding = Vertex::binding_description(0);
+        assert_eq!(binding.stride, 44);
+        assert_eq!(binding.input_rate, vk::VertexInputRate::VERTEX);
+    }
+
+    #[test]
+    fn instance_attributes_take_overrides() {
+        assert_eq!(attributes::<Instance>(1), [
+            (4, 1, vk::Format::R32G32B32A32_SFLOAT, 0),
+            (5, 1, vk::Format::R32G32B32A32_SFLOAT, 16),
+            (6, 1, vk::Format::R32G32B32A32_SFLOAT, 32),
+            (7, 1, vk::Format::R32G32B32A32_SFLOAT, 48),
+            (8, 1, vk::Format::R8G8B8A8_UNORM, 64),
+            (9, 1, vk::Format::R8G8B8A8_UINT, 68),
+        ]);
+        assert_eq!(Instance::INPUT_RATE, vk::VertexInputRate::INSTANCE);
+
+        let layout = VertexLayout::default().with::<Vertex>().with::<Instance>();
+        assert_eq!(layout.bindings.len(), 2);
+        assert_eq!(layout.attributes.len(), 10);
+    }
+}